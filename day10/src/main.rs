@@ -1,16 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 fn main() {
     let text = include_str!("../input");
 
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 enum Direction {
     North,
     South,
@@ -19,6 +20,13 @@ enum Direction {
 }
 
 impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::West,
+        Direction::South,
+        Direction::East,
+        Direction::North,
+    ];
+
     fn reverse(&self) -> Self {
         match self {
             Direction::North => Self::South,
@@ -44,7 +52,6 @@ impl Direction {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MapPiece {
-    Start,
     Ground,
     Segment(Direction, Direction),
 }
@@ -52,7 +59,6 @@ enum MapPiece {
 impl From<char> for MapPiece {
     fn from(value: char) -> Self {
         match value {
-            'S' => Self::Start,
             '.' => Self::Ground,
             '|' => Self::Segment(Direction::North, Direction::South),
             '-' => Self::Segment(Direction::West, Direction::East),
@@ -66,7 +72,39 @@ impl From<char> for MapPiece {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+impl MapPiece {
+    /// Builds the segment joining the two given directions, in the same
+    /// orientation the parser would produce for the matching character
+    fn joining(a: Direction, b: Direction) -> Self {
+        ['|', '-', 'L', 'J', '7', 'F']
+            .into_iter()
+            .map(MapPiece::from)
+            .find(|piece| piece.connects(a) && piece.connects(b))
+            .unwrap()
+    }
+
+    fn connects(&self, dir: Direction) -> bool {
+        match self {
+            MapPiece::Ground => false,
+            MapPiece::Segment(dir1, dir2) => *dir1 == dir || *dir2 == dir,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            MapPiece::Ground => '.',
+            MapPiece::Segment(Direction::North, Direction::South) => '|',
+            MapPiece::Segment(Direction::West, Direction::East) => '-',
+            MapPiece::Segment(Direction::North, Direction::East) => 'L',
+            MapPiece::Segment(Direction::North, Direction::West) => 'J',
+            MapPiece::Segment(Direction::West, Direction::South) => '7',
+            MapPiece::Segment(Direction::East, Direction::South) => 'F',
+            MapPiece::Segment(_, _) => '?',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Location {
     row: isize,
     col: isize,
@@ -99,17 +137,110 @@ impl Location {
     }
 
     fn adjacents(&self) -> impl Iterator<Item = Self> + '_ {
-        [
-            Direction::West,
-            Direction::South,
-            Direction::East,
-            Direction::North,
-        ]
-        .iter()
-        .map(|dir| self.move_in_dir(dir))
+        Direction::ALL.iter().map(|dir| self.move_in_dir(dir))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {}, col {}", self.row + 1, self.col + 1)
+    }
+}
+
+#[derive(Debug)]
+enum MapError {
+    NoStart,
+    MultipleStarts(Vec<Location>),
+    /// None of the shapes `S` could take close a loop through it
+    StartNotOnLoop(Location),
+    /// Several of the shapes `S` could take close a loop through it
+    AmbiguousStart(Location, Vec<MapPiece>),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::NoStart => write!(f, "no start tile 'S' in map"),
+            MapError::MultipleStarts(locs) => {
+                write!(f, "multiple start tiles 'S' in map, at")?;
+                for loc in locs {
+                    write!(f, " ({})", loc)?;
+                }
+                Ok(())
+            }
+            MapError::StartNotOnLoop(loc) => {
+                write!(f, "start tile at ({}) is not part of a closed loop", loc)
+            }
+            MapError::AmbiguousStart(loc, options) => {
+                write!(f, "start tile at ({}) is ambiguous, could be any of", loc)?;
+                for piece in options {
+                    write!(f, " '{}'", piece.to_char())?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
+/// A closed loop of pipe, listed in walking order from the first cell found
+struct PipeLoop {
+    cells: Vec<Location>,
+}
+
+impl PipeLoop {
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn contains(&self, loc: &Location) -> bool {
+        self.cells.contains(loc)
+    }
+}
+
+/// Follows the pipe out of `origin` until it either returns to `origin`, giving
+/// the closed loop, or runs into a dead end or a pipe that doesn't connect back.
+/// On failure gives every cell of the pipe through `origin`, walked out to both
+/// of its ends, none of which can be on a closed loop.
+fn trace_loop(
+    map: &HashMap<Location, (MapPiece, EnclosedStatus)>,
+    origin: Location,
+) -> Result<PipeLoop, Vec<Location>> {
+    let mut cells = vec![origin];
+    let Some((MapPiece::Segment(first_dir, second_dir), _)) = map.get(&origin) else {
+        return Err(cells);
+    };
+
+    // the walk has to come back into the origin through one of its own ends
+    if walk_pipe(map, origin, *first_dir, &mut cells)
+        .is_some_and(|dir| map[&origin].0.connects(dir.reverse()))
+    {
+        return Ok(PipeLoop { cells });
+    }
+    walk_pipe(map, origin, *second_dir, &mut cells);
+    Err(cells)
+}
+
+/// Walks the pipe leaving `origin` in `dir`, adding each cell to `cells`. Gives
+/// the direction the walk arrived back at `origin` in, if it gets there before
+/// a dead end or a pipe that doesn't connect back.
+fn walk_pipe(
+    map: &HashMap<Location, (MapPiece, EnclosedStatus)>,
+    origin: Location,
+    dir: Direction,
+    cells: &mut Vec<Location>,
+) -> Option<Direction> {
+    let mut walker = WalkerState::new(origin.move_in_dir(&dir), dir);
+    while walker.position != origin {
+        let (piece, _) = map.get(&walker.position)?;
+        if !piece.connects(walker.moved_from.reverse()) {
+            return None;
+        }
+        cells.push(walker.position);
+        walker.step(piece);
+    }
+    Some(walker.moved_from)
+}
+
 struct WalkerState {
     position: Location,
     moved_from: Direction,
@@ -125,7 +256,6 @@ impl WalkerState {
 
     fn step(&mut self, square: &MapPiece) {
         match square {
-            MapPiece::Start => panic!("Reached start square in step"),
             MapPiece::Ground => panic!("Reach Ground square in step"),
             MapPiece::Segment(dir1, dir2) => {
                 if self.moved_from.reverse() == *dir1 {
@@ -164,72 +294,118 @@ struct SolveState {
 }
 
 impl SolveState {
-    fn from_input(input: &str) -> Self {
+    fn from_input(input: &str) -> Result<Self, MapError> {
+        let mut starts = Vec::new();
         let mut map: HashMap<Location, (MapPiece, EnclosedStatus)> = input
             .lines()
             .enumerate()
             .flat_map(|(row_n, line)| {
-                line.char_indices().map(move |(col_n, c)| {
-                    (
-                        Location::new(row_n as isize, col_n as isize),
-                        (c.into(), EnclosedStatus::Unknown),
-                    )
-                })
+                line.char_indices()
+                    .map(move |(col_n, c)| (Location::new(row_n as isize, col_n as isize), c))
+            })
+            .map(|(loc, c)| {
+                if c == 'S' {
+                    starts.push(loc);
+                    (loc, (MapPiece::Ground, EnclosedStatus::Unknown))
+                } else {
+                    (loc, (c.into(), EnclosedStatus::Unknown))
+                }
             })
             .collect();
 
-        let mut start = Location::new(0, 0);
-        for (loc, (c, status)) in map.iter_mut() {
-            if *c == MapPiece::Start {
-                start = loc.clone();
-                *status = EnclosedStatus::Loop;
-            }
+        starts.sort_by_key(|loc| (loc.row, loc.col));
+        let start = match starts[..] {
+            [] => return Err(MapError::NoStart),
+            [start] => start,
+            _ => return Err(MapError::MultipleStarts(starts)),
+        };
+
+        let start_piece = Self::infer_start(&mut map, start)?;
+        map.insert(start, (start_piece, EnclosedStatus::Loop));
+
+        let MapPiece::Segment(dir1, dir2) = start_piece else {
+            unreachable!("inferred start is always a segment")
+        };
+        let path1 = WalkerState::new(start.move_in_dir(&dir1), dir1);
+        let path2 = WalkerState::new(start.move_in_dir(&dir2), dir2);
+        for loc in [path1.position, path2.position] {
+            map.entry(loc)
+                .and_modify(|(_, status)| *status = EnclosedStatus::Loop);
         }
 
-        let mut path1 = None;
-        let mut path2 = None;
-
-        for dir in [
-            Direction::West,
-            Direction::South,
-            Direction::East,
-            Direction::North,
-        ] {
-            let check_step = start.move_in_dir(&dir);
-            if let MapPiece::Segment(dir1, dir2) = map.get(&check_step).unwrap().0 {
-                if dir1 == dir.reverse() || dir2 == dir.reverse() {
-                    let state = WalkerState::new(check_step, dir);
-                    if path1.is_none() {
-                        path1 = Some(state);
-                        map.entry(check_step)
-                            .and_modify(|(_, status)| *status = EnclosedStatus::Loop);
-                    } else {
-                        path2 = Some(state);
-                        map.entry(check_step)
-                            .and_modify(|(_, status)| *status = EnclosedStatus::Loop);
-                        break; // filled both
-                    }
-                }
-            }
-        }
-
-        map.entry(start).and_modify(|(c, _)| {
-            *c = MapPiece::Segment(
-                path1.as_ref().unwrap().moved_from,
-                path2.as_ref().unwrap().moved_from,
-            )
-        });
-
-        Self {
+        Ok(Self {
             map,
             start,
-            path1: path1.unwrap(),
-            path2: path2.unwrap(),
+            path1,
+            path2,
             steps: 1,
             inside: None,
             row_count: input.lines().count() as isize,
             col_count: input.lines().next().unwrap().chars().count() as isize,
+        })
+    }
+
+    /// Works out which pipe is hidden under the start tile from the neighbours
+    /// that point back into it, keeping only the shapes that close a loop
+    /// through the start.
+    fn infer_start(
+        map: &mut HashMap<Location, (MapPiece, EnclosedStatus)>,
+        start: Location,
+    ) -> Result<MapPiece, MapError> {
+        let connected: Vec<Direction> = Direction::ALL
+            .into_iter()
+            .filter(|dir| {
+                map.get(&start.move_in_dir(dir))
+                    .is_some_and(|(piece, _)| piece.connects(dir.reverse()))
+            })
+            .collect();
+
+        let candidates: Vec<MapPiece> = connected
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| connected[i + 1..].iter().map(|b| MapPiece::joining(*a, *b)))
+            .collect();
+
+        let closing: Vec<MapPiece> = candidates
+            .into_iter()
+            .filter(|piece| {
+                map.insert(start, (*piece, EnclosedStatus::Unknown));
+                trace_loop(map, start).is_ok()
+            })
+            .collect();
+        map.insert(start, (MapPiece::Ground, EnclosedStatus::Unknown));
+
+        match closing[..] {
+            [] => Err(MapError::StartNotOnLoop(start)),
+            [piece] => Ok(piece),
+            _ => Err(MapError::AmbiguousStart(start, closing)),
+        }
+    }
+
+    /// Finds every closed loop of pipe in the map, ignoring any pipes that dead
+    /// end or lead into a pipe that doesn't connect back
+    fn find_loops(&self) -> Vec<PipeLoop> {
+        let mut seen: HashSet<Location> = HashSet::new();
+        let mut loops = Vec::new();
+
+        for row_n in 0..self.row_count {
+            for col_n in 0..self.col_count {
+                let loc = Location::new(row_n, col_n);
+                if seen.contains(&loc) {
+                    continue;
+                }
+
+                match trace_loop(&self.map, loc) {
+                    Ok(pipe_loop) => {
+                        seen.extend(pipe_loop.cells.iter().copied());
+                        loops.push(pipe_loop);
+                    }
+                    Err(walked) => seen.extend(walked),
+                }
+            }
         }
+
+        loops
     }
 
     fn lookup_piece(&self, loc: &Location) -> MapPiece {
@@ -279,9 +455,7 @@ impl SolveState {
         if let MapPiece::Segment(mut dir1, mut dir2) = self.lookup_piece(&loc) {
             if self.path1.moved_from.reverse() == dir2 {
                 // came from dir 2, swap them for this
-                let temp = dir1;
-                dir1 = dir2;
-                dir2 = temp;
+                std::mem::swap(&mut dir1, &mut dir2);
             }
 
             if dir1.clockwise() == dir2 {
@@ -356,14 +530,11 @@ impl SolveState {
                 let cell_type = self.map.get(&spread_from).unwrap().1;
                 if cell_type == EnclosedStatus::LeftSide || cell_type == EnclosedStatus::RightSide {
                     for adj in spread_from.adjacents() {
-                        match self.map.get_mut(&adj) {
-                            Some((_, status)) => {
-                                if *status == EnclosedStatus::Unknown {
-                                    *status = cell_type;
-                                    cells_changed += 1;
-                                }
+                        if let Some((_, status)) = self.map.get_mut(&adj) {
+                            if *status == EnclosedStatus::Unknown {
+                                *status = cell_type;
+                                cells_changed += 1;
                             }
-                            None => {}
                         }
                     }
                 }
@@ -379,7 +550,7 @@ impl SolveState {
 
     fn find_outside_type(&self) -> EnclosedStatus {
         for col in 0..self.col_count {
-            match self.map.get(&Location { row: 0, col: col }).unwrap().1 {
+            match self.map.get(&Location { row: 0, col }).unwrap().1 {
                 EnclosedStatus::RightSide => return EnclosedStatus::RightSide,
                 EnclosedStatus::LeftSide => return EnclosedStatus::LeftSide,
                 _ => {}
@@ -389,7 +560,7 @@ impl SolveState {
                 .map
                 .get(&Location {
                     row: self.row_count - 1,
-                    col: col,
+                    col,
                 })
                 .unwrap()
                 .1
@@ -401,7 +572,7 @@ impl SolveState {
         }
 
         for row in 0..self.row_count {
-            match self.map.get(&Location { row: row, col: 0 }).unwrap().1 {
+            match self.map.get(&Location { row, col: 0 }).unwrap().1 {
                 EnclosedStatus::RightSide => return EnclosedStatus::RightSide,
                 EnclosedStatus::LeftSide => return EnclosedStatus::LeftSide,
                 _ => {}
@@ -410,7 +581,7 @@ impl SolveState {
             match self
                 .map
                 .get(&Location {
-                    row: row,
+                    row,
                     col: self.col_count - 1,
                 })
                 .unwrap()
@@ -445,14 +616,46 @@ impl SolveState {
 }
 
 fn part1(text: &str) {
-    let mut state = SolveState::from_input(text);
+    let mut state = match SolveState::from_input(text) {
+        Ok(state) => state,
+        Err(e) => {
+            println!("Invalid map: {}", e);
+            return;
+        }
+    };
+
+    let loops = state.find_loops();
+    println!(
+        "Found {} closed loop{}:",
+        loops.len(),
+        if loops.len() == 1 { "" } else { "s" }
+    );
+    for pipe_loop in &loops {
+        println!(
+            "  length {} from ({}){}",
+            pipe_loop.len(),
+            pipe_loop.cells[0],
+            if pipe_loop.contains(&state.start) {
+                ", through start"
+            } else {
+                ""
+            }
+        );
+    }
+
     while !state.step_both() {}
 
     println!("{}", state.steps);
 }
 
 fn part2(text: &str) {
-    let mut state = SolveState::from_input(text);
+    let mut state = match SolveState::from_input(text) {
+        Ok(state) => state,
+        Err(e) => {
+            println!("Invalid map: {}", e);
+            return;
+        }
+    };
     state.mark_loop();
 
     state.tag_loop_adjacent_cells();
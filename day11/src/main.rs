@@ -1,15 +1,29 @@
-use na::DMatrix;
-
-extern crate nalgebra as na;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [cmd, growth_factor, galaxy_path, histogram_path] = &args[..] {
+        if cmd == "export" {
+            export(
+                text,
+                growth_factor.parse().expect("Invalid growth factor"),
+                galaxy_path,
+                histogram_path,
+            )
+            .expect("Failed to write export");
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -28,6 +42,7 @@ impl Space {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 struct Point {
     row: usize,
     col: usize,
@@ -37,72 +52,289 @@ impl Point {
     fn new(row: usize, col: usize) -> Self {
         Self { row, col }
     }
+}
+
+/// The unexpanded image, holding only where the galaxies are and which rows
+/// and columns are empty
+struct Universe {
+    galaxies: Vec<Point>,
+    empty_rows: Vec<bool>,
+    empty_cols: Vec<bool>,
+}
+
+impl Universe {
+    fn from_input(text: &str) -> Self {
+        let row_count = text.lines().count();
+        let col_count = text.lines().next().unwrap().chars().count();
+
+        let mut galaxies = Vec::new();
+        let mut empty_rows = vec![true; row_count];
+        let mut empty_cols = vec![true; col_count];
+
+        for (rown, line) in text.lines().enumerate() {
+            for (coln, c) in line.chars().enumerate() {
+                if Space::from_char(&c) == Space::Galaxy {
+                    galaxies.push(Point::new(rown, coln));
+                    empty_rows[rown] = false;
+                    empty_cols[coln] = false;
+                }
+            }
+        }
+
+        Self {
+            galaxies,
+            empty_rows,
+            empty_cols,
+        }
+    }
+
+    /// Sum of the distances between every pair of galaxies once expanded.
+    ///
+    /// Each axis is handled separately: with galaxies counted per row (or
+    /// column) and positions taken from the expansion's prefix sums, a single
+    /// pass accumulates how far each galaxy is from all the ones before it.
+    fn total_distance(&self, expansion: &Expansion) -> u128 {
+        let mut row_counts = vec![0u128; self.empty_rows.len()];
+        let mut col_counts = vec![0u128; self.empty_cols.len()];
+        for galaxy in &self.galaxies {
+            row_counts[galaxy.row] += 1;
+            col_counts[galaxy.col] += 1;
+        }
+
+        axis_distance(&row_counts, &expansion.row_offsets)
+            + axis_distance(&col_counts, &expansion.col_offsets)
+    }
 
-    fn manhattan_dist(&self, other: &Self) -> usize {
-        self.row.abs_diff(other.row) + self.col.abs_diff(other.col)
+    fn index(&self, expansion: &Expansion) -> GalaxyIndex {
+        GalaxyIndex::new(
+            self.galaxies
+                .iter()
+                .map(|galaxy| {
+                    Point::new(
+                        expansion.row_offsets[galaxy.row],
+                        expansion.col_offsets[galaxy.col],
+                    )
+                })
+                .collect(),
+        )
     }
 }
 
-fn day11_core(text: &str, growth_factor: usize) -> usize {
-    let space = DMatrix::from_row_iterator(
-        text.lines().count(),
-        text.lines().next().unwrap().chars().count(),
-        text.lines()
-            .flat_map(|line| line.chars().map(|c| Space::from_char(&c))),
-    );
+/// Sum of pairwise distances along one axis, given how many galaxies sit at
+/// each index and the expanded position of each index
+fn axis_distance(counts: &[u128], offsets: &[usize]) -> u128 {
+    let mut seen = 0;
+    let mut seen_offset_sum = 0;
+    let mut sum = 0;
 
-    let cols_to_grow: Vec<_> = space
-        .column_iter()
-        .enumerate()
-        .filter_map(|(n, col)| {
-            if col.iter().all(|s| *s == Space::Empty) {
-                Some(n)
-            } else {
-                None
+    for (count, offset) in counts.iter().zip(offsets) {
+        let offset = *offset as u128;
+        sum += count * (offset * seen - seen_offset_sum);
+        seen += count;
+        seen_offset_sum += count * offset;
+    }
+
+    sum
+}
+
+/// Where each row and column of the image ends up after expansion, stored as
+/// prefix sums of the width every row and column grows to
+struct Expansion {
+    row_offsets: Vec<usize>,
+    col_offsets: Vec<usize>,
+}
+
+impl Expansion {
+    /// Every empty row and column grows to `growth_factor` times its width
+    fn uniform(universe: &Universe, growth_factor: usize) -> Self {
+        Self::per_line(universe, |_| growth_factor, |_| growth_factor)
+    }
+
+    /// Empty row `n` grows to `row_factor(n)` rows, and empty column `n` to
+    /// `col_factor(n)` columns. Rows and columns holding a galaxy don't grow.
+    fn per_line(
+        universe: &Universe,
+        row_factor: impl Fn(usize) -> usize,
+        col_factor: impl Fn(usize) -> usize,
+    ) -> Self {
+        Self {
+            row_offsets: prefix_offsets(&universe.empty_rows, row_factor),
+            col_offsets: prefix_offsets(&universe.empty_cols, col_factor),
+        }
+    }
+}
+
+/// Galaxy positions after a particular expansion, for answering questions about
+/// individual galaxies rather than the grand total. Galaxies are numbered from
+/// 0 in reading order.
+struct GalaxyIndex {
+    positions: Vec<Point>,
+    /// Galaxy numbers sorted by expanded column, for pruning neighbour scans
+    by_col: Vec<usize>,
+    /// The two galaxies at each end of both diagonals, which between them
+    /// include the furthest galaxy from any other
+    extremes: Vec<usize>,
+}
+
+impl GalaxyIndex {
+    fn new(positions: Vec<Point>) -> Self {
+        let mut by_col: Vec<usize> = (0..positions.len()).collect();
+        by_col.sort_by_key(|n| positions[*n].col);
+
+        let mut extremes = Vec::new();
+        let diagonals: [fn(&Point) -> isize; 2] = [
+            |p| (p.row + p.col) as isize,
+            |p| p.row as isize - p.col as isize,
+        ];
+        for diagonal in diagonals {
+            let mut sorted: Vec<usize> = (0..positions.len()).collect();
+            sorted.sort_by_key(|n| diagonal(&positions[*n]));
+            extremes.extend(sorted.iter().take(2));
+            extremes.extend(sorted.iter().rev().take(2));
+        }
+        extremes.sort();
+        extremes.dedup();
+
+        Self {
+            positions,
+            by_col,
+            extremes,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn distance(&self, i: usize, j: usize) -> usize {
+        let (a, b) = (self.positions[i], self.positions[j]);
+        a.row.abs_diff(b.row) + a.col.abs_diff(b.col)
+    }
+
+    /// Closest other galaxy to galaxy `i`, and how far away it is. Scans
+    /// outwards by column and stops once the column gap alone is further than
+    /// the best found so far.
+    fn nearest(&self, i: usize) -> Option<(usize, usize)> {
+        let col = self.positions[i].col;
+        let pos = self
+            .by_col
+            .partition_point(|n| self.positions[*n].col < col);
+
+        let mut best: Option<(usize, usize)> = None;
+        let mut consider = |j: usize| -> bool {
+            if best.is_some_and(|(_, dist)| self.positions[j].col.abs_diff(col) > dist) {
+                return false;
             }
-        })
-        .collect();
+            let dist = self.distance(i, j);
+            if j != i && best.is_none_or(|(best_j, best_dist)| (dist, j) < (best_dist, best_j)) {
+                best = Some((j, dist));
+            }
+            true
+        };
 
-    let rows_to_grow: Vec<_> = space
-        .row_iter()
-        .enumerate()
-        .filter_map(|(n, row)| {
-            if row.iter().all(|s| *s == Space::Empty) {
-                Some(n)
-            } else {
-                None
+        let mut right = self.by_col[pos..].iter();
+        let mut left = self.by_col[..pos].iter().rev();
+        let (mut right_open, mut left_open) = (true, true);
+        while right_open || left_open {
+            if right_open {
+                right_open = right.next().is_some_and(|j| consider(*j));
             }
-        })
-        .collect();
-
-    let mut galaxies: Vec<Point> = Vec::new();
-    for (rown, row) in space.row_iter().enumerate() {
-        for (coln, space) in row.iter().enumerate() {
-            if *space == Space::Galaxy {
-                let rown = rown
-                    + rows_to_grow
-                        .iter()
-                        .filter(|growth_row| rown > **growth_row)
-                        .count()
-                        * (growth_factor - 1);
-                let coln = coln
-                    + cols_to_grow
-                        .iter()
-                        .filter(|growth_col| coln > **growth_col)
-                        .count()
-                        * (growth_factor - 1);
-                galaxies.push(Point::new(rown, coln));
+            if left_open {
+                left_open = left.next().is_some_and(|j| consider(*j));
             }
         }
+
+        best
     }
 
-    let mut sum = 0;
-    for i in 0..galaxies.len() {
-        for j in i..galaxies.len() {
-            sum += galaxies[i].manhattan_dist(&galaxies[j]);
+    /// Furthest galaxy from galaxy `i`, and how far away it is. Manhattan
+    /// distance is the largest of the differences along the two diagonals, so
+    /// only the galaxies at the extremes of each diagonal need checking.
+    fn farthest(&self, i: usize) -> Option<(usize, usize)> {
+        self.extremes
+            .iter()
+            .filter(|j| **j != i)
+            .map(|j| (*j, self.distance(i, *j)))
+            .max_by_key(|(j, dist)| (*dist, std::cmp::Reverse(*j)))
+    }
+
+    /// Number of galaxy pairs at each distance, with distances rounded down to
+    /// a multiple of `bucket_width`. This looks at every pair, so is quadratic
+    /// in the number of galaxies.
+    fn histogram(&self, bucket_width: usize) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for i in 0..self.len() {
+            for j in i + 1..self.len() {
+                let bucket = self.distance(i, j) / bucket_width * bucket_width;
+                *histogram.entry(bucket).or_insert(0) += 1;
+            }
         }
+        histogram
     }
-    sum
+
+    /// One line per galaxy with its expanded position and nearest and furthest
+    /// neighbours. Galaxies are numbered from 1 here to match the puzzle text.
+    fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "galaxy,row,col,nearest,nearest_distance,farthest,farthest_distance"
+        )?;
+        for i in 0..self.len() {
+            let pos = self.positions[i];
+            write!(out, "{},{},{}", i + 1, pos.row, pos.col)?;
+            for neighbour in [self.nearest(i), self.farthest(i)] {
+                match neighbour {
+                    Some((j, dist)) => write!(out, ",{},{}", j + 1, dist)?,
+                    None => write!(out, ",,")?,
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_histogram_csv(histogram: &BTreeMap<usize, usize>, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "distance,pairs")?;
+    for (distance, pairs) in histogram {
+        writeln!(out, "{},{}", distance, pairs)?;
+    }
+    Ok(())
+}
+
+fn prefix_offsets(empty: &[bool], factor: impl Fn(usize) -> usize) -> Vec<usize> {
+    empty
+        .iter()
+        .enumerate()
+        .scan(0, |offset, (n, is_empty)| {
+            let here = *offset;
+            *offset += if *is_empty { factor(n) } else { 1 };
+            Some(here)
+        })
+        .collect()
+}
+
+fn day11_core(text: &str, growth_factor: usize) -> u128 {
+    let universe = Universe::from_input(text);
+    universe.total_distance(&Expansion::uniform(&universe, growth_factor))
+}
+
+fn export(
+    text: &str,
+    growth_factor: usize,
+    galaxy_path: &str,
+    histogram_path: &str,
+) -> io::Result<()> {
+    let universe = Universe::from_input(text);
+    let index = universe.index(&Expansion::uniform(&universe, growth_factor));
+
+    let mut out = BufWriter::new(File::create(galaxy_path)?);
+    index.write_csv(&mut out)?;
+    out.flush()?;
+
+    let mut out = BufWriter::new(File::create(histogram_path)?);
+    write_histogram_csv(&index.histogram(1), &mut out)?;
+    out.flush()
 }
 
 fn part1(text: &str) {
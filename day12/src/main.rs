@@ -1,13 +1,203 @@
+use std::collections::HashMap;
+
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        if cmd == "enumerate" {
+            let max = args
+                .get(1)
+                .map_or(10, |max| max.parse().expect("Invalid arrangement limit"));
+            enumerate(text, max);
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Spring {
+    Operational,
+    Damaged,
+    Unknown,
 }
 
-fn part1(text: &str) {}
+impl Spring {
+    fn from_char(c: &char) -> Self {
+        match c {
+            '.' => Self::Operational,
+            '#' => Self::Damaged,
+            '?' => Self::Unknown,
+            _ => panic!("Unexpected char in Spring::from_char: {c}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SpringRow {
+    springs: Vec<Spring>,
+    groups: Vec<usize>,
+}
+
+impl SpringRow {
+    fn from_line(line: &str) -> Self {
+        let (springs, groups) = line
+            .split_once(' ')
+            .expect("Missing group list in spring row");
+
+        Self {
+            springs: springs.chars().map(|c| Spring::from_char(&c)).collect(),
+            groups: groups.split(',').map(|n| n.parse().unwrap()).collect(),
+        }
+    }
+
+    /// Repeats the row `times` times, with an extra unknown spring joining each
+    /// copy of the springs
+    fn unfold(&self, times: usize) -> Self {
+        let mut springs = Vec::with_capacity(self.springs.len() * times + times);
+        for n in 0..times {
+            if n != 0 {
+                springs.push(Spring::Unknown);
+            }
+            springs.extend(&self.springs);
+        }
+
+        Self {
+            springs,
+            groups: self.groups.repeat(times),
+        }
+    }
+
+    /// Whether the next damaged group can start at `pos`: it must fit in the
+    /// row, cover no operational springs, and not run straight into another
+    /// damaged spring
+    fn group_fits(&self, pos: usize, len: usize) -> bool {
+        pos + len <= self.springs.len()
+            && !self.springs[pos..pos + len].contains(&Spring::Operational)
+            && self.springs.get(pos + len) != Some(&Spring::Damaged)
+    }
+
+    pub fn arrangements(&self) -> u128 {
+        self.count_from(0, 0, &mut HashMap::new())
+    }
 
-fn part2(text: &str) {}
+    /// Number of ways to place `groups[group..]` in `springs[pos..]`, with the
+    /// results for each `(pos, group)` kept in `memo`
+    fn count_from(
+        &self,
+        pos: usize,
+        group: usize,
+        memo: &mut HashMap<(usize, usize), u128>,
+    ) -> u128 {
+        if group == self.groups.len() {
+            return if self.springs[pos.min(self.springs.len())..].contains(&Spring::Damaged) {
+                0
+            } else {
+                1
+            };
+        }
+        if pos >= self.springs.len() {
+            return 0;
+        }
+        if let Some(count) = memo.get(&(pos, group)) {
+            return *count;
+        }
+
+        let mut count = 0;
+        if self.springs[pos] != Spring::Damaged {
+            count += self.count_from(pos + 1, group, memo);
+        }
+        let len = self.groups[group];
+        if self.group_fits(pos, len) {
+            // skip the spring after the group as well, it has to be operational
+            count += self.count_from(pos + len + 1, group + 1, memo);
+        }
+
+        memo.insert((pos, group), count);
+        count
+    }
+
+    /// Every concrete arrangement of the row, written out with `.` and `#`.
+    /// Only sensible for rows with few arrangements, as they are all built.
+    pub fn enumerate(&self) -> Vec<String> {
+        let mut memo = HashMap::new();
+        let mut found = Vec::new();
+        self.enumerate_from(0, 0, &mut String::new(), &mut memo, &mut found);
+        found
+    }
+
+    fn enumerate_from(
+        &self,
+        pos: usize,
+        group: usize,
+        current: &mut String,
+        memo: &mut HashMap<(usize, usize), u128>,
+        found: &mut Vec<String>,
+    ) {
+        // only follow branches the counter says lead somewhere
+        if self.count_from(pos, group, memo) == 0 {
+            return;
+        }
+        if group == self.groups.len() {
+            let mut done = current.clone();
+            while done.len() < self.springs.len() {
+                done.push('.');
+            }
+            found.push(done);
+            return;
+        }
+
+        let len_before = current.len();
+        if self.springs[pos] != Spring::Damaged {
+            current.push('.');
+            self.enumerate_from(pos + 1, group, current, memo, found);
+            current.truncate(len_before);
+        }
+        let len = self.groups[group];
+        if self.group_fits(pos, len) {
+            current.extend(std::iter::repeat_n('#', len));
+            if pos + len < self.springs.len() {
+                current.push('.');
+            }
+            self.enumerate_from(pos + len + 1, group + 1, current, memo, found);
+            current.truncate(len_before);
+        }
+    }
+}
+
+fn enumerate(text: &str, max: u128) {
+    for line in text.lines() {
+        let row = SpringRow::from_line(line);
+        let count = row.arrangements();
+        println!("{line}: {count} arrangements");
+        if count <= max {
+            for arrangement in row.enumerate() {
+                println!("  {arrangement}");
+            }
+        }
+    }
+}
+
+fn part1(text: &str) {
+    println!(
+        "{}",
+        text.lines()
+            .map(|line| SpringRow::from_line(line).arrangements())
+            .sum::<u128>()
+    );
+}
+
+fn part2(text: &str) {
+    println!(
+        "{}",
+        text.lines()
+            .map(|line| SpringRow::from_line(line).unfold(5).arrangements())
+            .sum::<u128>()
+    );
+}
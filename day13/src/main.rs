@@ -4,11 +4,22 @@ use na::DMatrix;
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        if cmd == "axes" {
+            let max = args
+                .get(1)
+                .map_or(1, |max| max.parse().expect("Invalid mismatch limit"));
+            list_axes(text, max);
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -43,66 +54,114 @@ impl Pattern {
         }
     }
 
-    fn row_reflect(&self) -> usize {
-        let rows = self.pattern.row_iter().collect::<Vec<_>>();
-        for i in 1..self.pattern.nrows() {
-            let left = &rows[0..i];
-            let right = &rows[i..];
-            if left.iter().rev().zip(right.iter()).all(|(l, r)| l == r) {
-                return i;
-            }
+    /// Every axis the pattern reflects across with at most `max_mismatches`
+    /// cells differing from their mirror image. Square patterns are also
+    /// checked across both diagonals.
+    pub fn reflections(&self, max_mismatches: usize) -> Vec<Reflection> {
+        let (nrows, ncols) = self.pattern.shape();
+        let mut found = Vec::new();
+
+        for i in 1..nrows {
+            let pairs = (0..i)
+                .rev()
+                .zip(i..nrows)
+                .flat_map(|(r1, r2)| (0..ncols).map(move |c| ((r1, c), (r2, c))));
+            found.extend(self.check_axis(Axis::Row(i), pairs, max_mismatches));
         }
-        0
-    }
 
-    fn col_reflect(&self) -> usize {
-        let cols = self.pattern.column_iter().collect::<Vec<_>>();
-        for i in 1..self.pattern.ncols() {
-            let left = &cols[0..i];
-            let right = &cols[i..];
-            if left.iter().rev().zip(right.iter()).all(|(l, r)| l == r) {
-                return i;
-            }
+        for i in 1..ncols {
+            let pairs = (0..i)
+                .rev()
+                .zip(i..ncols)
+                .flat_map(|(c1, c2)| (0..nrows).map(move |r| ((r, c1), (r, c2))));
+            found.extend(self.check_axis(Axis::Col(i), pairs, max_mismatches));
         }
-        0
-    }
 
-    pub fn reflect(&self) -> usize {
-        self.col_reflect() + 100 * self.row_reflect()
-    }
+        if nrows == ncols {
+            let n = nrows;
+            let pairs = (0..n).flat_map(|r| (r + 1..n).map(move |c| ((r, c), (c, r))));
+            found.extend(self.check_axis(Axis::MainDiagonal, pairs, max_mismatches));
 
-    pub fn smudged_row_reflect(&self) -> usize {
-        let rows = self.pattern.row_iter().collect::<Vec<_>>();
-        for i in 1..self.pattern.nrows() {
-            let left = &rows[0..i];
-            let right = &rows[i..];
-            if left.iter().rev().zip(right.iter()).fold(0, |acc, (l, r)| {
-                l.iter().zip(r.iter()).filter(|(a, b)| a != b).count() + acc
-            }) == 1
-            {
-                return i;
-            }
+            let pairs =
+                (0..n).flat_map(|r| (0..n - 1 - r).map(move |c| ((r, c), (n - 1 - c, n - 1 - r))));
+            found.extend(self.check_axis(Axis::AntiDiagonal, pairs, max_mismatches));
         }
-        0
+
+        found
     }
 
-    pub fn smudged_col_reflect(&self) -> usize {
-        let cols = self.pattern.column_iter().collect::<Vec<_>>();
-        for i in 1..self.pattern.ncols() {
-            let left = &cols[0..i];
-            let right = &cols[i..];
-            if left.iter().rev().zip(right.iter()).fold(0, |acc, (l, r)| {
-                l.iter().zip(r.iter()).filter(|(a, b)| a != b).count() + acc
-            }) == 1
-            {
-                return i;
+    /// Compares each pair of mirrored cells, giving up once there are more
+    /// than `max_mismatches` differences
+    fn check_axis(
+        &self,
+        axis: Axis,
+        pairs: impl Iterator<Item = ((usize, usize), (usize, usize))>,
+        max_mismatches: usize,
+    ) -> Option<Reflection> {
+        let mut mismatches = Vec::new();
+        for (a, b) in pairs {
+            if self.pattern[a] != self.pattern[b] {
+                if mismatches.len() == max_mismatches {
+                    return None;
+                }
+                mismatches.push(a);
             }
         }
-        0
+
+        Some(Reflection { axis, mismatches })
+    }
+
+    /// Puzzle summary from the first row axis and the first column axis with
+    /// exactly `mismatches` differences: columns left of the vertical axis,
+    /// plus 100 times the rows above the horizontal one
+    pub fn summarize(&self, mismatches: usize) -> usize {
+        let reflections: Vec<Reflection> = self
+            .reflections(mismatches)
+            .into_iter()
+            .filter(|reflection| reflection.mismatches.len() == mismatches)
+            .collect();
+        let row = reflections
+            .iter()
+            .find_map(|reflection| match reflection.axis {
+                Axis::Row(n) => Some(n),
+                _ => None,
+            });
+        let col = reflections
+            .iter()
+            .find_map(|reflection| match reflection.axis {
+                Axis::Col(n) => Some(n),
+                _ => None,
+            });
+        col.unwrap_or(0) + 100 * row.unwrap_or(0)
     }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Axis {
+    /// Between rows `n - 1` and `n`
+    Row(usize),
+    /// Between columns `n - 1` and `n`
+    Col(usize),
+    /// Top left to bottom right
+    MainDiagonal,
+    /// Top right to bottom left
+    AntiDiagonal,
+}
 
-    pub fn smudged_reflect(&self) -> usize {
-        self.smudged_col_reflect() + 100 * self.smudged_row_reflect()
+#[derive(Clone, Debug)]
+struct Reflection {
+    axis: Axis,
+    /// `(row, col)` of each cell that differs from its mirror image, taken from
+    /// the top or left side of the axis
+    mismatches: Vec<(usize, usize)>,
+}
+
+fn list_axes(text: &str, max_mismatches: usize) {
+    for (n, block) in text.split("\n\n").enumerate() {
+        println!("Pattern {}:", n + 1);
+        for reflection in Pattern::from_block(block).reflections(max_mismatches) {
+            println!("  {:?} {:?}", reflection.axis, reflection.mismatches);
+        }
     }
 }
 
@@ -110,7 +169,7 @@ fn part1(text: &str) {
     println!(
         "{}",
         text.split("\n\n")
-            .map(|block| Pattern::from_block(block).reflect())
+            .map(|block| Pattern::from_block(block).summarize(0))
             .sum::<usize>()
     );
 }
@@ -119,7 +178,7 @@ fn part2(text: &str) {
     println!(
         "{}",
         text.split("\n\n")
-            .map(|block| Pattern::from_block(block).smudged_reflect())
+            .map(|block| Pattern::from_block(block).summarize(1))
             .sum::<usize>()
     );
 }
@@ -0,0 +1,194 @@
+//! Cycle detection for deterministic state machines: anything that can be
+//! stepped from one state to the next will eventually repeat if it has finitely
+//! many states, after which its future is known.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The shape of a sequence `x0, f(x0), f(f(x0)), ...` that eventually repeats.
+/// The first `lead_in` states are never seen again, after which the sequence
+/// loops every `period` steps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub lead_in: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    /// The earliest step with the same state as step `n`
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.lead_in {
+            n
+        } else {
+            self.lead_in + (n - self.lead_in) % self.period
+        }
+    }
+}
+
+/// Finds the cycle reached by repeatedly applying `step` to `initial`. None of
+/// the searches terminate if the states never repeat.
+pub struct CycleFinder<S, F> {
+    initial: S,
+    step: F,
+}
+
+impl<S, F> CycleFinder<S, F>
+where
+    S: Clone + Eq,
+    F: Fn(&S) -> S,
+{
+    pub fn new(initial: S, step: F) -> Self {
+        Self { initial, step }
+    }
+
+    /// Brent's algorithm: only two states are held at a time, and `step` is run
+    /// fewer times than with Floyd's
+    pub fn brent(&self) -> Cycle {
+        let mut power = 1;
+        let mut period = 1;
+        let mut tortoise = self.initial.clone();
+        let mut hare = (self.step)(&self.initial);
+        while tortoise != hare {
+            if power == period {
+                tortoise = hare.clone();
+                power *= 2;
+                period = 0;
+            }
+            hare = (self.step)(&hare);
+            period += 1;
+        }
+
+        // with the hare a full period ahead, they first meet at the loop start
+        let mut tortoise = self.initial.clone();
+        let mut hare = self.state_at_exact(period);
+        let mut lead_in = 0;
+        while tortoise != hare {
+            tortoise = (self.step)(&tortoise);
+            hare = (self.step)(&hare);
+            lead_in += 1;
+        }
+
+        Cycle { lead_in, period }
+    }
+
+    /// Floyd's tortoise and hare: only two states are held at a time
+    pub fn floyd(&self) -> Cycle {
+        let mut tortoise = (self.step)(&self.initial);
+        let mut hare = (self.step)(&tortoise);
+        while tortoise != hare {
+            tortoise = (self.step)(&tortoise);
+            hare = (self.step)(&(self.step)(&hare));
+        }
+
+        let mut tortoise = self.initial.clone();
+        let mut lead_in = 0;
+        while tortoise != hare {
+            tortoise = (self.step)(&tortoise);
+            hare = (self.step)(&hare);
+            lead_in += 1;
+        }
+
+        let mut period = 1;
+        let mut hare = (self.step)(&tortoise);
+        while tortoise != hare {
+            hare = (self.step)(&hare);
+            period += 1;
+        }
+
+        Cycle { lead_in, period }
+    }
+
+    /// The state after exactly `n` steps, without using any known cycle
+    fn state_at_exact(&self, n: usize) -> S {
+        let mut state = self.initial.clone();
+        for _ in 0..n {
+            state = (self.step)(&state);
+        }
+        state
+    }
+
+    /// The state after `n` steps, stepping only as far as the first time that
+    /// state appears in `cycle`
+    pub fn state_at(&self, cycle: &Cycle, n: usize) -> S {
+        self.state_at_exact(cycle.reduce(n))
+    }
+}
+
+impl<S, F> CycleFinder<S, F>
+where
+    S: Clone + Eq + Hash,
+    F: Fn(&S) -> S,
+{
+    /// Remembers every state seen, so each step is run only once at the cost
+    /// of holding the whole lead-in and first loop in memory
+    pub fn hashed(&self) -> Cycle {
+        let mut seen: HashMap<S, usize> = HashMap::new();
+        let mut state = self.initial.clone();
+        loop {
+            if let Some(first) = seen.get(&state) {
+                return Cycle {
+                    lead_in: *first,
+                    period: seen.len() - first,
+                };
+            }
+            let next = (self.step)(&state);
+            seen.insert(state, seen.len());
+            state = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(x: &u32) -> u32 {
+        (x * x + 1) % 255
+    }
+
+    /// The cycle found by listing states until one comes round again
+    fn listed(initial: u32) -> Cycle {
+        let mut states = vec![initial];
+        loop {
+            let next = step(states.last().unwrap());
+            if let Some(first) = states.iter().position(|state| *state == next) {
+                return Cycle {
+                    lead_in: first,
+                    period: states.len() - first,
+                };
+            }
+            states.push(next);
+        }
+    }
+
+    #[test]
+    fn searches_agree() {
+        for initial in [0, 2, 3, 7, 100] {
+            let finder = CycleFinder::new(initial, step);
+            let expected = listed(initial);
+            assert_eq!(finder.brent(), expected, "brent from {}", initial);
+            assert_eq!(finder.floyd(), expected, "floyd from {}", initial);
+            assert_eq!(finder.hashed(), expected, "hashed from {}", initial);
+        }
+    }
+
+    #[test]
+    fn state_at_skips_ahead() {
+        let finder = CycleFinder::new(3, step);
+        // 3, 10, 101, 2, 5, 26, 167, 95, 101, ...
+        let cycle = finder.brent();
+        assert_eq!(
+            cycle,
+            Cycle {
+                lead_in: 2,
+                period: 6
+            }
+        );
+        for n in 0..200 {
+            assert_eq!(finder.state_at(&cycle, n), finder.state_at_exact(n));
+        }
+        // 1_000_000_000 is 2 more than a multiple of 6 past the lead-in, so
+        // it matches step 4
+        assert_eq!(finder.state_at(&cycle, 1_000_000_000), 5);
+    }
+}
@@ -1,16 +1,30 @@
-extern crate nalgebra as na;
-use std::collections::HashMap;
+mod cycle;
 
-use na::DMatrix;
+use std::fmt;
+
+use cycle::CycleFinder;
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        if cmd == "run" {
+            let program = TiltProgram::from_str(args.get(1).expect("Missing tilt program"));
+            let times = args
+                .get(2)
+                .map_or(1, |times| times.parse().expect("Invalid repeat count"));
+            let mode = args.get(3).map_or("brent", String::as_str);
+            run_program(text, &program, times, mode);
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -30,8 +44,6 @@ impl Square {
         }
     }
 
-    // This function is used in debugging, allow it to be deadcode
-    #[allow(dead_code)]
     fn as_char(&self) -> char {
         match self {
             Square::Empty => '.',
@@ -41,194 +53,329 @@ impl Square {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::West,
+        Direction::South,
+        Direction::East,
+    ];
+
+    fn from_char(c: &char) -> Self {
+        match c {
+            'N' => Self::North,
+            'W' => Self::West,
+            'S' => Self::South,
+            'E' => Self::East,
+            _ => panic!("Unexpected char in Direction::from_char: {c}"),
+        }
+    }
+}
+
+/// The platform as two bitboards, one for round rocks and one for cubes. Each
+/// row is packed into `stride` words, with column `c` at bit `c % 64` of word
+/// `c / 64`.
+#[derive(Clone, PartialEq, Eq, Hash)]
 struct Platform {
-    map: DMatrix<Square>,
-    past: HashMap<DMatrix<Square>, usize>,
+    width: usize,
+    height: usize,
+    stride: usize,
+    round: Vec<u64>,
+    cube: Vec<u64>,
 }
 
 impl Platform {
     fn from_input(input: &str) -> Self {
-        Self {
-            map: DMatrix::from_row_iterator(
-                input.lines().count(),
-                input.lines().next().unwrap().chars().count(),
-                input
-                    .lines()
-                    .flat_map(|line| line.chars().map(|c| Square::from_char(&c))),
-            ),
-            past: HashMap::new(),
-        }
-    }
+        let height = input.lines().count();
+        let width = input.lines().next().unwrap().chars().count();
+        let stride = width.div_ceil(64);
 
-    fn tilt_north(&mut self) {
-        let rows = self.map.nrows();
-        for mut col in self.map.column_iter_mut() {
-            let mut open = 0;
-            for i in 0..rows {
-                match col[i] {
+        let mut round = vec![0; height * stride];
+        let mut cube = vec![0; height * stride];
+        for (row, line) in input.lines().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                let (word, bit) = (row * stride + col / 64, 1 << (col % 64));
+                match Square::from_char(&c) {
                     Square::Empty => {}
-                    Square::Round => {
-                        if i > open {
-                            // need to move the rock
-                            col[open] = Square::Round;
-                            col[i] = Square::Empty;
-                        }
-                        open = open + 1;
-                    }
-                    Square::Cube => {
-                        // set open to next cell
-                        open = i + 1
-                    }
+                    Square::Round => round[word] |= bit,
+                    Square::Cube => cube[word] |= bit,
                 }
             }
         }
+
+        Self {
+            width,
+            height,
+            stride,
+            round,
+            cube,
+        }
     }
 
-    fn tilt_south(&mut self) {
-        let rows = self.map.nrows();
-        for mut col in self.map.column_iter_mut() {
-            let mut open: isize = rows as isize - 1;
-            for i in (0..rows).rev() {
-                match col[i] {
-                    Square::Empty => {}
-                    Square::Round => {
-                        if i < open as usize {
-                            // need to move the rock
-                            col[open as usize] = Square::Round;
-                            col[i] = Square::Empty;
-                        }
-                        open = open - 1;
-                    }
-                    Square::Cube => {
-                        // set open to next cell
-                        open = i as isize - 1
-                    }
-                }
-            }
+    fn row_words(&self, row: usize) -> std::ops::Range<usize> {
+        row * self.stride..(row + 1) * self.stride
+    }
+
+    fn square(&self, row: usize, col: usize) -> Square {
+        let (word, bit) = (row * self.stride + col / 64, 1 << (col % 64));
+        if self.round[word] & bit != 0 {
+            Square::Round
+        } else if self.cube[word] & bit != 0 {
+            Square::Cube
+        } else {
+            Square::Empty
         }
     }
 
-    fn tilt_west(&mut self) {
-        let cols = self.map.ncols();
-        for mut row in self.map.row_iter_mut() {
-            let mut open = 0;
-            for i in 0..cols {
-                match row[i] {
-                    Square::Empty => {}
-                    Square::Round => {
-                        if i > open {
-                            // need to move the rock
-                            row[open] = Square::Round;
-                            row[i] = Square::Empty;
-                        }
-                        open = open + 1;
-                    }
-                    Square::Cube => {
-                        // set open to next cell
-                        open = i + 1
-                    }
+    /// Rolls every round rock as far as it will go in `dir`, in a single sweep
+    /// from the edge being tilted towards
+    fn tilt(&mut self, dir: Direction) {
+        match dir {
+            Direction::North | Direction::South => self.tilt_columns(dir),
+            Direction::West | Direction::East => self.tilt_rows(dir),
+        }
+    }
+
+    /// Keeps the next free row of each column, counted from the edge being
+    /// tilted towards. A cube frees the row past it, and a round rock lands in
+    /// the free row and takes it.
+    fn tilt_columns(&mut self, dir: Direction) {
+        let row_at = |n: usize| {
+            if dir == Direction::North {
+                n
+            } else {
+                self.height - 1 - n
+            }
+        };
+        let mut free = vec![0; self.width];
+        for n in 0..self.height {
+            let row = row_at(n);
+            for (word_n, word) in self.row_words(row).enumerate() {
+                let mut cubes = self.cube[word];
+                while cubes != 0 {
+                    free[word_n * 64 + cubes.trailing_zeros() as usize] = n + 1;
+                    cubes &= cubes - 1;
+                }
+
+                let mut rocks = std::mem::take(&mut self.round[word]);
+                while rocks != 0 {
+                    let col = word_n * 64 + rocks.trailing_zeros() as usize;
+                    let target = row_at(free[col]) * self.stride + word_n;
+                    self.round[target] |= 1 << (col % 64);
+                    free[col] += 1;
+                    rocks &= rocks - 1;
                 }
             }
         }
     }
 
-    fn tile_east(&mut self) {
-        let cols = self.map.ncols();
-        for mut row in self.map.row_iter_mut() {
-            let mut open: isize = cols as isize - 1;
-            for i in (0..cols).rev() {
-                match row[i] {
-                    Square::Empty => {}
-                    Square::Round => {
-                        if i < open as usize {
-                            // need to move the rock
-                            row[open as usize] = Square::Round;
-                            row[i] = Square::Empty;
-                        }
-                        open = open - 1;
-                    }
-                    Square::Cube => {
-                        // set open to next cell
-                        open = i as isize - 1
-                    }
+    /// Counts the round rocks between each pair of cubes in a row, then packs
+    /// them against the cube or edge on the side being tilted towards
+    fn tilt_rows(&mut self, dir: Direction) {
+        let mut packed = vec![0; self.stride];
+        for row in 0..self.height {
+            let words = self.row_words(row);
+            packed.fill(0);
+
+            let mut start = 0;
+            for end in set_cols(&self.cube[words.clone()]).chain([self.width]) {
+                let rocks = count_bits(&self.round[words.clone()], start, end);
+                if dir == Direction::West {
+                    set_bits(&mut packed, start, start + rocks);
+                } else {
+                    set_bits(&mut packed, end - rocks, end);
                 }
+                start = end + 1;
             }
+            self.round[words].copy_from_slice(&packed);
+        }
+    }
+
+    /// Runs each tilt of the program in turn, calling `after_tilt` with the
+    /// platform after each one
+    fn run(&mut self, program: &TiltProgram, mut after_tilt: impl FnMut(Direction, &Self)) {
+        for dir in &program.tilts {
+            self.tilt(*dir);
+            after_tilt(*dir, self);
         }
     }
 
-    fn cycle(&mut self) -> bool {
-        self.past.insert(self.map.clone(), self.past.len());
-        self.tilt_north();
-        self.tilt_west();
-        self.tilt_south();
-        self.tile_east();
-        self.past.contains_key(&self.map)
-    }
-
-    fn calculate_load(&self) -> usize {
-        let rows = self.map.nrows();
-        self.map
-            .row_iter()
-            .enumerate()
-            .map(|(row_n, row)| {
-                //println!();
-                row.iter()
-                    .filter_map(|square| {
-                        //print!("{}", square.as_char());
-                        if *square == Square::Round {
-                            Some(rows - row_n)
+    /// Total load on the beams along `edge`, where each round rock weighs as
+    /// much as its distance from the opposite edge, counting its own square
+    fn load_on(&self, edge: Direction) -> usize {
+        match edge {
+            Direction::North | Direction::South => (0..self.height)
+                .map(|row| {
+                    let rocks = self
+                        .row_words(row)
+                        .map(|word| self.round[word].count_ones() as usize)
+                        .sum::<usize>();
+                    let weight = if edge == Direction::North {
+                        self.height - row
+                    } else {
+                        row + 1
+                    };
+                    rocks * weight
+                })
+                .sum(),
+            Direction::West | Direction::East => (0..self.height)
+                .flat_map(|row| self.row_words(row).enumerate())
+                .map(|(word_n, word)| {
+                    let mut bits = self.round[word];
+                    let mut load = 0;
+                    while bits != 0 {
+                        let col = word_n * 64 + bits.trailing_zeros() as usize;
+                        load += if edge == Direction::West {
+                            self.width - col
                         } else {
-                            None
-                        }
-                    })
-                    .sum::<usize>()
-            })
-            .sum::<usize>()
+                            col + 1
+                        };
+                        bits &= bits - 1;
+                    }
+                    load
+                })
+                .sum(),
+        }
     }
+}
 
-    // used for debug, don't warn for deadcode
-    #[allow(dead_code)]
-    fn print_map(&self) {
-        for row in self.map.row_iter() {
-            for square in row.iter() {
-                print!("{}", square.as_char());
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                write!(f, "{}", self.square(row, col).as_char())?;
             }
-            println!();
+            writeln!(f)?;
         }
+        Ok(())
     }
 }
 
-fn part1(text: &str) {
-    let mut platform = Platform::from_input(text);
+/// A sequence of tilts, such as `NWSE` for the spin cycle
+struct TiltProgram {
+    tilts: Vec<Direction>,
+}
 
-    platform.tilt_north();
+impl TiltProgram {
+    fn from_str(program: &str) -> Self {
+        Self {
+            tilts: program.chars().map(|c| Direction::from_char(&c)).collect(),
+        }
+    }
 
-    println!("{}", platform.calculate_load());
+    fn spin_cycle() -> Self {
+        Self::from_str("NWSE")
+    }
 }
 
-fn part2(text: &str) {
-    let mut platform = Platform::from_input(text);
+/// Columns of the set bits in a packed row, lowest first
+fn set_cols(words: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    words.iter().enumerate().flat_map(|(word_n, word)| {
+        let mut bits = *word;
+        std::iter::from_fn(move || {
+            let col = word_n * 64 + bits.trailing_zeros() as usize;
+            (bits != 0).then(|| {
+                bits &= bits - 1;
+                col
+            })
+        })
+    })
+}
 
-    for _ in 0..1_000_000_000 {
-        if platform.cycle() {
-            break;
-        }
+/// Bits of word `word_n` of a packed row that are in columns `start..end`
+fn range_mask(word_n: usize, start: usize, end: usize) -> u64 {
+    let from = start.saturating_sub(word_n * 64).min(64);
+    let to = end.saturating_sub(word_n * 64).min(64);
+    if from >= to {
+        0
+    } else {
+        (u64::MAX >> (64 - (to - from))) << from
     }
+}
 
-    let cycle_len = platform.past.len() - platform.past.get(&platform.map).unwrap();
-    let lead_length = platform.past.len() - cycle_len;
+/// How many bits are set in columns `start..end` of a packed row
+fn count_bits(words: &[u64], start: usize, end: usize) -> usize {
+    (start / 64..end.div_ceil(64))
+        .map(|word_n| (words[word_n] & range_mask(word_n, start, end)).count_ones() as usize)
+        .sum()
+}
 
-    let n = (0..lead_length)
-        .chain(((lead_length)..(lead_length + cycle_len)).cycle())
-        .nth(1_000_000_000)
-        .unwrap();
+/// Sets columns `start..end` of a packed row
+fn set_bits(words: &mut [u64], start: usize, end: usize) {
+    for (word_n, word) in words
+        .iter_mut()
+        .enumerate()
+        .take(end.div_ceil(64))
+        .skip(start / 64)
+    {
+        *word |= range_mask(word_n, start, end);
+    }
+}
 
-    for (map, position) in platform.past.iter() {
-        if *position == n {
-            platform.map = map.clone();
-            break;
+/// Runs `program` on the platform `times` times, then reports the load on each
+/// edge. With mode `show`, the platform is drawn after every tilt, otherwise
+/// any repetition in the platform's states is found with the `brent`, `floyd`
+/// or `hashed` search and used to skip ahead.
+fn run_program(text: &str, program: &TiltProgram, times: usize, mode: &str) {
+    let platform = if mode == "show" {
+        let mut platform = Platform::from_input(text);
+        println!("Initial:\n{platform}");
+        for n in 0..times {
+            platform.run(program, |dir, platform| {
+                println!("After {} {:?}:\n{}", n + 1, dir, platform)
+            });
         }
+        platform
+    } else {
+        let finder = CycleFinder::new(Platform::from_input(text), |platform: &Platform| {
+            let mut platform = platform.clone();
+            platform.run(program, |_, _| {});
+            platform
+        });
+        let cycle = match mode {
+            "brent" => finder.brent(),
+            "floyd" => finder.floyd(),
+            "hashed" => finder.hashed(),
+            _ => panic!("Unknown run mode {mode}"),
+        };
+        println!(
+            "Repeats every {} runs after the first {}",
+            cycle.period, cycle.lead_in
+        );
+        finder.state_at(&cycle, times)
+    };
+
+    for edge in Direction::ALL {
+        println!("Load on {:?} edge: {}", edge, platform.load_on(edge));
     }
+}
+
+fn part1(text: &str) {
+    let mut platform = Platform::from_input(text);
+
+    platform.tilt(Direction::North);
+
+    println!("{}", platform.load_on(Direction::North));
+}
+
+fn part2(text: &str) {
+    let spin_cycle = TiltProgram::spin_cycle();
+    let finder = CycleFinder::new(Platform::from_input(text), |platform: &Platform| {
+        let mut platform = platform.clone();
+        platform.run(&spin_cycle, |_, _| {});
+        platform
+    });
+
+    let cycle = finder.brent();
+    let platform = finder.state_at(&cycle, 1_000_000_000);
 
-    println!("{}", platform.calculate_load());
+    println!("{}", platform.load_on(Direction::North));
 }
@@ -0,0 +1,150 @@
+//! The Holiday ASCII String Helper Manual Arrangement Procedure as a general
+//! map: a fixed row of buckets, each holding its entries in insertion order.
+//! Replacing a value keeps its slot, removing one closes the gap behind it.
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+/// The HASH algorithm, kept in a `u64` so it can be reduced to any number of
+/// buckets. Reducing modulo 256 gives the puzzle's 8 bit value.
+pub fn run_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |current, new| {
+        current.wrapping_add(*new as u64).wrapping_mul(17)
+    })
+}
+
+/// Keys are hashed and compared through their borrowed form `Q`, so a map
+/// keyed by `String` can be searched with a `&str`
+pub struct HolidayMap<K, V, H, Q: ?Sized = K> {
+    buckets: Vec<Vec<(K, V)>>,
+    hasher: H,
+    key_form: PhantomData<fn(&Q)>,
+}
+
+impl<K, V, H, Q> HolidayMap<K, V, H, Q>
+where
+    K: Borrow<Q>,
+    Q: Eq + ?Sized,
+    H: Fn(&Q) -> u64,
+{
+    pub fn new(bucket_count: usize, hasher: H) -> Self {
+        assert!(bucket_count > 0, "HolidayMap needs at least one bucket");
+        Self {
+            buckets: (0..bucket_count).map(|_| Vec::new()).collect(),
+            hasher,
+            key_form: PhantomData,
+        }
+    }
+
+    pub fn bucket_of(&self, key: &Q) -> usize {
+        ((self.hasher)(key) % self.buckets.len() as u64) as usize
+    }
+
+    fn slot_of(&self, key: &Q) -> (usize, Option<usize>) {
+        let bucket = self.bucket_of(key);
+        let slot = self.buckets[bucket]
+            .iter()
+            .position(|(k, _)| k.borrow() == key);
+        (bucket, slot)
+    }
+
+    /// Puts `value` in the slot already holding `key`, returning the old value,
+    /// or adds it to the back of its bucket if there isn't one
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.slot_of(key.borrow()) {
+            (bucket, Some(slot)) => {
+                Some(std::mem::replace(&mut self.buckets[bucket][slot].1, value))
+            }
+            (bucket, None) => {
+                self.buckets[bucket].push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Takes `key` out of its bucket, moving the entries behind it forward
+    pub fn remove(&mut self, key: &Q) -> Option<V> {
+        let (bucket, slot) = self.slot_of(key);
+        let (_, value) = self.buckets[bucket].remove(slot?);
+        Some(value)
+    }
+
+    /// Entries of a single bucket, front to back
+    pub fn bucket(&self, bucket: usize) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets[bucket].iter().map(|(k, v)| (k, v))
+    }
+
+    /// Every entry as `(bucket, slot, key, value)`, in bucket then slot order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &K, &V)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .flat_map(|(bucket, entries)| {
+                entries
+                    .iter()
+                    .enumerate()
+                    .map(move |(slot, (k, v))| (bucket, slot, k, v))
+            })
+    }
+
+    /// Sum over every entry of its value times its one-based bucket and slot
+    pub fn focusing_power(&self) -> u64
+    where
+        V: Copy + Into<u64>,
+    {
+        self.iter()
+            .map(|(bucket, slot, _, value)| {
+                (bucket as u64 + 1) * (slot as u64 + 1) * (*value).into()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Runs the same random inserts and removes against a `HashMap`, checking
+    /// every result agrees and that each bucket keeps its entries in the order
+    /// they were first inserted
+    #[test]
+    fn matches_hashmap() {
+        let mut map: HolidayMap<String, u32, _, str> =
+            HolidayMap::new(7, |key: &str| run_hash(key.as_bytes()));
+        let mut expected: HashMap<String, u32> = HashMap::new();
+        let mut order: Vec<Vec<String>> = vec![Vec::new(); 7];
+
+        // xorshift, so the sequence is the same every run
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+
+        for _ in 0..20_000 {
+            let key = format!("k{}", random(40));
+            let bucket = map.bucket_of(&key);
+            if random(3) == 0 {
+                assert_eq!(map.remove(&key), expected.remove(&key));
+                order[bucket].retain(|k| *k != key);
+            } else {
+                let value = random(10) as u32;
+                let old = expected.insert(key.clone(), value);
+                assert_eq!(map.insert(key.clone(), value), old);
+                if old.is_none() {
+                    order[bucket].push(key.clone());
+                }
+            }
+        }
+
+        let entries: Vec<(usize, usize, &String, &u32)> = map.iter().collect();
+        assert_eq!(entries.len(), expected.len());
+        for (bucket, slot, key, value) in entries {
+            assert_eq!(order[bucket][slot], *key);
+            assert_eq!(expected[key], *value);
+        }
+    }
+}
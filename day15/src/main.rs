@@ -1,111 +1,188 @@
-use std::num::Wrapping;
+mod holiday_map;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use holiday_map::{run_hash, HolidayMap};
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("trace") => {
+            print!("{}", trace_steps(text));
+            return;
+        }
+        Some("diff") => {
+            let path = args.get(1).expect("Missing file to compare against");
+            let other = std::fs::read_to_string(path).expect("Failed to read comparison file");
+            let differences = trace_steps(text).diff(&trace_steps(&other));
+            if differences.is_empty() {
+                println!("No differences");
+            }
+            for difference in differences {
+                println!("{difference}");
+            }
+            return;
+        }
+        _ => {}
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
-}
-
-fn run_hash(step: &str) -> Wrapping<u8> {
-    step.bytes()
-        .map(|n| Wrapping(n))
-        .fold(Wrapping(0), |current, new| (current + new) * Wrapping(17))
-}
-
-struct Lens {
-    label: String,
-    focal_length: u32,
+    part2(text);
 }
 
-#[derive(Default)]
-struct Box {
-    number: u32,
-    contents: Vec<Lens>,
+struct BoxSet {
+    /// Lenses keyed by label, with their focal length
+    boxes: HolidayMap<String, u32, fn(&str) -> u64, str>,
+    trace: Option<Trace>,
 }
 
-impl Box {
-    fn new(number: u32) -> Self {
+impl BoxSet {
+    fn new() -> Self {
         Self {
-            number,
-            contents: Vec::new(),
+            boxes: HolidayMap::new(256, |label| run_hash(label.as_bytes())),
+            trace: None,
         }
     }
 
-    fn remove_lens(&mut self, label: &str) {
-        if let Some(index) = self.contents.iter().position(|lens| lens.label == label) {
-            self.contents.remove(index);
+    /// A box set that records the box touched by every rule it runs
+    fn with_trace() -> Self {
+        Self {
+            trace: Some(Trace::default()),
+            ..Self::new()
         }
     }
 
-    fn add_lens(&mut self, lens: Lens) {
-        if let Some(index) = self
-            .contents
-            .iter()
-            .position(|contents| contents.label == lens.label)
-        {
-            self.contents[index] = lens;
+    fn run_rule(&mut self, rule: &str) {
+        let label = if let Some((label, focus)) = rule.split_once('=') {
+            self.boxes.insert(label.to_string(), focus.parse().unwrap());
+            label
+        } else if let Some((label, _)) = rule.split_once('-') {
+            self.boxes.remove(label);
+            label
         } else {
-            self.contents.push(lens);
+            unreachable!()
+        };
+
+        if let Some(trace) = &mut self.trace {
+            let number = self.boxes.bucket_of(label);
+            trace.steps.push(TraceStep {
+                rule: rule.to_string(),
+                number,
+                contents: self
+                    .boxes
+                    .bucket(number)
+                    .map(|(label, focus)| (label.clone(), *focus))
+                    .collect(),
+            });
         }
     }
 
-    fn calculate_power(&self) -> u32 {
-        self.contents
-            .iter()
-            .enumerate()
-            .map(|(slot, lens)| lens.focal_length * (slot as u32 + 1) * (self.number + 1))
-            .sum()
+    fn calculate_power(&self) -> u64 {
+        self.boxes.focusing_power()
     }
 }
 
-struct BoxSet {
-    boxes: Vec<Box>,
+/// The contents of the box a rule touched, straight after running it
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TraceStep {
+    rule: String,
+    number: usize,
+    contents: Vec<(String, u32)>,
 }
 
-impl BoxSet {
-    fn new() -> Self {
-        Self {
-            boxes: (0..256).map(|i| Box::new(i)).collect(),
+#[derive(Default)]
+struct Trace {
+    steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    /// Describes each step where the two runs differ, either in the rule run or
+    /// in what the touched box held afterwards
+    fn diff(&self, other: &Trace) -> Vec<String> {
+        let mut differences = Vec::new();
+        for (n, (ours, theirs)) in self.steps.iter().zip(&other.steps).enumerate() {
+            if ours == theirs {
+                continue;
+            }
+            differences.push(format!(
+                "Step {}: \"{}\" gave Box {}: {} but \"{}\" gave Box {}: {}",
+                n + 1,
+                ours.rule,
+                ours.number,
+                format_lenses(&ours.contents),
+                theirs.rule,
+                theirs.number,
+                format_lenses(&theirs.contents),
+            ));
+        }
+        if self.steps.len() != other.steps.len() {
+            differences.push(format!(
+                "Runs have different lengths: {} and {} steps",
+                self.steps.len(),
+                other.steps.len()
+            ));
         }
+        differences
     }
+}
 
-    fn run_rule(&mut self, rule: &str) {
-        if let Some((label, focus)) = rule.split_once("=") {
-            let lens = Lens {
-                label: label.to_string(),
-                focal_length: focus.parse().unwrap(),
-            };
-            let index = run_hash(label).0 as usize;
-            self.boxes[index].add_lens(lens);
-        } else if let Some((label, _)) = rule.split_once("-") {
-            let index = run_hash(label).0 as usize;
-            self.boxes[index].remove_lens(label);
-        } else {
-            unreachable!()
+fn format_lenses(contents: &[(String, u32)]) -> String {
+    if contents.is_empty() {
+        return "empty".to_string();
+    }
+    contents
+        .iter()
+        .map(|(label, focus)| format!("[{label} {focus}]"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lists every non-empty box after each step, as laid out in the puzzle text
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut boxes: BTreeMap<usize, &[(String, u32)]> = BTreeMap::new();
+        for step in &self.steps {
+            boxes.insert(step.number, &step.contents);
+
+            writeln!(f, "After \"{}\":", step.rule)?;
+            for (number, contents) in &boxes {
+                if !contents.is_empty() {
+                    writeln!(f, "Box {}: {}", number, format_lenses(contents))?;
+                }
+            }
+            writeln!(f)?;
         }
+        Ok(())
     }
+}
 
-    fn calculate_power(&self) -> u32 {
-        self.boxes.iter().map(|b| b.calculate_power()).sum()
+fn trace_steps(text: &str) -> Trace {
+    let mut boxes = BoxSet::with_trace();
+    for step in text.trim().split(',') {
+        boxes.run_rule(step);
     }
+    boxes.trace.unwrap()
 }
+
 fn part1(text: &str) {
     println!(
         "{}",
         text.trim()
-            .split(",")
-            .map(|step| run_hash(step).0 as u32)
-            .sum::<u32>()
+            .split(',')
+            .map(|step| run_hash(step.as_bytes()) % 256)
+            .sum::<u64>()
     );
 }
 
 fn part2(text: &str) {
     let mut boxes = BoxSet::new();
-    for step in text.trim().split(",") {
+    for step in text.trim().split(',') {
         boxes.run_rule(step);
     }
 
@@ -1,16 +1,38 @@
-use std::{cmp::max, collections::HashMap};
+use rayon::prelude::*;
+use std::{cmp::Reverse, collections::HashMap, fmt, thread, time::Duration};
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("show") => {
+            show(text);
+            return;
+        }
+        Some("animate") => {
+            let delay = args
+                .get(1)
+                .map_or(50, |ms| ms.parse().expect("Invalid delay in milliseconds"));
+            animate(text, Duration::from_millis(delay));
+            return;
+        }
+        Some("simulate") => {
+            println!("Part 2:");
+            part2(text, true);
+            return;
+        }
+        _ => {}
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text, false);
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 enum Direction {
     Up,
     Down,
@@ -51,7 +73,7 @@ impl Location {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 struct BeamTip {
     location: Location,
     facing: Direction,
@@ -97,11 +119,51 @@ impl ContraptionSegment {
             _ => panic!("Unexpected character {c} found in from_char"),
         }
     }
+
+    fn as_char(&self) -> char {
+        match self {
+            Self::Empty => '.',
+            Self::HorizontalSplitter => '-',
+            Self::VerticalSplitter => '|',
+            Self::DownMirror => '\\',
+            Self::UpMirror => '/',
+        }
+    }
+
+    /// Directions a beam leaves in after arriving facing `facing`
+    fn outgoing(&self, facing: Direction) -> Vec<Direction> {
+        // rename locally to reduce long lines that don't say much
+        use Direction as Dir;
+
+        match self {
+            Self::Empty => vec![facing], // continue in line
+            Self::HorizontalSplitter => match facing {
+                Dir::Up | Dir::Down => vec![Dir::Left, Dir::Right], // split
+                Dir::Left | Dir::Right => vec![facing],             // continue in line
+            },
+            Self::VerticalSplitter => match facing {
+                Dir::Left | Dir::Right => vec![Dir::Up, Dir::Down], // split
+                Dir::Up | Dir::Down => vec![facing],                // continue in line
+            },
+            Self::DownMirror => match facing {
+                Dir::Up => vec![Dir::Left],
+                Dir::Down => vec![Dir::Right],
+                Dir::Left => vec![Dir::Up],
+                Dir::Right => vec![Dir::Down],
+            },
+            Self::UpMirror => match facing {
+                Dir::Up => vec![Dir::Right],
+                Dir::Down => vec![Dir::Left],
+                Dir::Left => vec![Dir::Down],
+                Dir::Right => vec![Dir::Up],
+            },
+        }
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// The beams that have passed through a tile during one run
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 struct Tile {
-    object: ContraptionSegment,
     facing_up: bool,
     facing_down: bool,
     facing_left: bool,
@@ -109,20 +171,6 @@ struct Tile {
 }
 
 impl Tile {
-    fn new(object: ContraptionSegment) -> Self {
-        Self {
-            object,
-            facing_up: false,
-            facing_down: false,
-            facing_left: false,
-            facing_right: false,
-        }
-    }
-
-    fn from_char(c: &char) -> Self {
-        Self::new(ContraptionSegment::from_char(c))
-    }
-
     fn passed_in_direction_mut(&mut self, dir: Direction) -> &mut bool {
         match dir {
             Direction::Up => &mut self.facing_up,
@@ -132,15 +180,43 @@ impl Tile {
         }
     }
 
+    fn passed_in_direction(&self, dir: Direction) -> bool {
+        match dir {
+            Direction::Up => self.facing_up,
+            Direction::Down => self.facing_down,
+            Direction::Left => self.facing_left,
+            Direction::Right => self.facing_right,
+        }
+    }
+
     fn is_energized(&self) -> bool {
         self.facing_up || self.facing_down || self.facing_left || self.facing_right
     }
 
-    fn handle_beam(&mut self, beam: &BeamTip) -> Vec<BeamTip> {
-        // rename locally to reduce long lines that don't say much
-        use ContraptionSegment as CS;
-        use Direction as Dir;
+    /// How an empty tile is drawn in the puzzle: an arrow for a single beam
+    /// direction, otherwise the number of directions that passed through
+    fn beam_char(&self) -> char {
+        let passed: Vec<Direction> = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+        .into_iter()
+        .filter(|dir| self.passed_in_direction(*dir))
+        .collect();
+
+        match passed[..] {
+            [] => '.',
+            [Direction::Up] => '^',
+            [Direction::Down] => 'v',
+            [Direction::Left] => '<',
+            [Direction::Right] => '>',
+            _ => char::from_digit(passed.len() as u32, 10).unwrap(),
+        }
+    }
 
+    fn handle_beam(&mut self, object: ContraptionSegment, beam: &BeamTip) -> Vec<BeamTip> {
         // Don't do anything if it's already been seen
         let is_duplicate = self.passed_in_direction_mut(beam.facing);
         if *is_duplicate {
@@ -148,43 +224,23 @@ impl Tile {
         }
         *is_duplicate = true;
 
-        // Handle each case
-        match self.object {
-            CS::Empty => vec![beam.continue_in_line()], // continue in line
-            CS::HorizontalSplitter => match beam.facing {
-                Dir::Up | Dir::Down => {
-                    vec![beam.move_in_dir(&Dir::Left), beam.move_in_dir(&Dir::Right)]
-                } // split
-                Dir::Left | Dir::Right => vec![beam.continue_in_line()], // continue in line
-            },
-            CS::VerticalSplitter => match beam.facing {
-                Dir::Left | Dir::Right => {
-                    vec![beam.move_in_dir(&Dir::Up), beam.move_in_dir(&Dir::Down)]
-                } // split
-                Dir::Up | Dir::Down => vec![beam.continue_in_line()], // continue in line
-            },
-            CS::DownMirror => match beam.facing {
-                Dir::Up => vec![beam.move_in_dir(&Dir::Left)],
-                Dir::Down => vec![beam.move_in_dir(&Dir::Right)],
-                Dir::Left => vec![beam.move_in_dir(&Dir::Up)],
-                Dir::Right => vec![beam.move_in_dir(&Dir::Down)],
-            },
-            CS::UpMirror => match beam.facing {
-                Dir::Up => vec![beam.move_in_dir(&Dir::Right)],
-                Dir::Down => vec![beam.move_in_dir(&Dir::Left)],
-                Dir::Left => vec![beam.move_in_dir(&Dir::Down)],
-                Dir::Right => vec![beam.move_in_dir(&Dir::Up)],
-            },
-        }
+        object
+            .outgoing(beam.facing)
+            .iter()
+            .map(|dir| beam.move_in_dir(dir))
+            .collect()
     }
 }
 
-struct ContraptionState {
-    map: HashMap<Location, Tile>,
-    beams: Vec<BeamTip>,
+/// The layout of mirrors and splitters, which never changes, so it can be
+/// shared between any number of runs
+struct Contraption {
+    map: HashMap<Location, ContraptionSegment>,
+    nrows: isize,
+    ncols: isize,
 }
 
-impl ContraptionState {
+impl Contraption {
     fn from_input(input: &str) -> Self {
         Self {
             map: input
@@ -194,17 +250,61 @@ impl ContraptionState {
                     line.char_indices().map(move |(col, c)| {
                         (
                             Location::new(row as isize, col as isize),
-                            Tile::from_char(&c),
+                            ContraptionSegment::from_char(&c),
                         )
                     })
                 })
                 .collect(),
-            beams: vec![BeamTip::new(Location::new(0, 0), Direction::Right)],
+            nrows: input.lines().count() as isize,
+            ncols: input.lines().next().unwrap().chars().count() as isize,
         }
     }
 
-    fn set_starting_beam(&mut self, beam: BeamTip) {
-        self.beams = vec![beam];
+    /// Every beam that can enter from the edge, heading into the contraption
+    fn edge_starts(&self) -> Vec<BeamTip> {
+        (0..self.ncols)
+            .flat_map(|col| {
+                [
+                    BeamTip::new(Location::new(0, col), Direction::Down),
+                    BeamTip::new(Location::new(self.nrows - 1, col), Direction::Up),
+                ]
+            })
+            .chain((0..self.nrows).flat_map(|row| {
+                [
+                    BeamTip::new(Location::new(row, 0), Direction::Right),
+                    BeamTip::new(Location::new(row, self.ncols - 1), Direction::Left),
+                ]
+            }))
+            .collect()
+    }
+
+    /// The edge start energizing the most tiles according to `count`, with
+    /// the starts evaluated in parallel. Ties go to the earliest start.
+    fn best_start(&self, count: impl Fn(BeamTip) -> usize + Sync) -> (BeamTip, usize) {
+        self.edge_starts()
+            .into_par_iter()
+            .enumerate()
+            .map(|(n, start)| (n, start, count(start)))
+            .max_by_key(|(n, _, energized)| (*energized, Reverse(*n)))
+            .map(|(_, start, energized)| (start, energized))
+            .unwrap()
+    }
+}
+
+/// A single run of beams through a shared contraption
+struct ContraptionState<'a> {
+    contraption: &'a Contraption,
+    tiles: HashMap<Location, Tile>,
+    beams: Vec<BeamTip>,
+}
+
+impl<'a> ContraptionState<'a> {
+    fn new(contraption: &'a Contraption, start: BeamTip) -> Self {
+        Self {
+            contraption,
+            tiles: HashMap::new(),
+            beams: vec![start],
+        }
     }
 
     fn has_beams(&self) -> bool {
@@ -213,8 +313,9 @@ impl ContraptionState {
 
     fn run_single_beam(&mut self) {
         if let Some(beam) = self.beams.pop() {
-            if let Some(tile) = self.map.get_mut(&beam.location) {
-                self.beams.append(&mut tile.handle_beam(&beam))
+            if let Some(object) = self.contraption.map.get(&beam.location) {
+                let tile = self.tiles.entry(beam.location).or_default();
+                self.beams.append(&mut tile.handle_beam(*object, &beam))
             }
         }
     }
@@ -226,50 +327,287 @@ impl ContraptionState {
     }
 
     fn count_energized(&self) -> usize {
-        self.map.values().filter(|tile| tile.is_energized()).count()
+        self.tiles
+            .values()
+            .filter(|tile| tile.is_energized())
+            .count()
+    }
+
+    fn tile(&self, loc: &Location) -> Tile {
+        self.tiles.get(loc).copied().unwrap_or_default()
+    }
+
+    /// The energized tiles as `#`, everything else as `.`
+    fn render_energized(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.contraption.nrows {
+            for col in 0..self.contraption.ncols {
+                let energized = self.tile(&Location::new(row, col)).is_energized();
+                out.push(if energized { '#' } else { '.' });
+            }
+            out.push('\n');
+        }
+        out
     }
 }
 
-fn part1(text: &str) {
-    let mut contraption = ContraptionState::from_input(text);
-    contraption.run_beams();
-    println!("{}", contraption.count_energized());
+/// Draws the beam paths over the contraption, with mirrors and splitters shown
+/// as themselves and empty tiles showing the beams that crossed them
+impl fmt::Display for ContraptionState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..self.contraption.nrows {
+            for col in 0..self.contraption.ncols {
+                let loc = Location::new(row, col);
+                let c = match self.contraption.map[&loc] {
+                    ContraptionSegment::Empty => self.tile(&loc).beam_char(),
+                    object => object.as_char(),
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Beam propagation as a graph between mirrors and splitters. Each node is a
+/// beam arriving at one of them heading in a particular direction, and owns
+/// the cells lit on the way to the next mirror or splitter. Beams loop, so
+/// nodes are grouped into strongly connected components, and the cells lit
+/// from each component are worked out once and shared by every start.
+struct BeamGraph {
+    ncols: isize,
+    nodes: HashMap<BeamTip, usize>,
+    component_of: Vec<usize>,
+    /// Bitset over all cells for each component
+    reach: Vec<Vec<u64>>,
+}
+
+impl BeamGraph {
+    fn new(contraption: &Contraption) -> Self {
+        let map = &contraption.map;
+        let ncols = contraption.ncols;
+        let words = (contraption.nrows * ncols) as usize / 64 + 1;
+
+        let mut nodes = HashMap::new();
+        for (loc, object) in map {
+            if *object != ContraptionSegment::Empty {
+                for dir in [
+                    Direction::Up,
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                ] {
+                    nodes.insert(BeamTip::new(*loc, dir), nodes.len());
+                }
+            }
+        }
+
+        let mut node_cells = vec![Vec::new(); nodes.len()];
+        let mut edges = vec![Vec::new(); nodes.len()];
+        for (tip, n) in &nodes {
+            node_cells[*n].push(cell_index(ncols, &tip.location));
+            for dir in map[&tip.location].outgoing(tip.facing) {
+                let (cells, next) = Self::trace(map, &nodes, ncols, tip.move_in_dir(&dir));
+                node_cells[*n].extend(cells);
+                edges[*n].extend(next);
+            }
+        }
+
+        let components = strongly_connected_components(&edges);
+        let mut component_of = vec![0; nodes.len()];
+        for (c, members) in components.iter().enumerate() {
+            for n in members {
+                component_of[*n] = c;
+            }
+        }
+
+        // components come out after everything they lead to, so each one
+        // only needs to merge in sets that are already complete
+        let mut reach: Vec<Vec<u64>> = Vec::with_capacity(components.len());
+        for (c, members) in components.iter().enumerate() {
+            let mut lit = vec![0u64; words];
+            for n in members {
+                for cell in &node_cells[*n] {
+                    lit[cell / 64] |= 1 << (cell % 64);
+                }
+                for next in &edges[*n] {
+                    let next_c = component_of[*next];
+                    if next_c != c {
+                        for (word, other) in lit.iter_mut().zip(&reach[next_c]) {
+                            *word |= other;
+                        }
+                    }
+                }
+            }
+            reach.push(lit);
+        }
+
+        Self {
+            ncols,
+            nodes,
+            component_of,
+            reach,
+        }
+    }
+
+    /// Follows a beam through empty tiles from `tip`, giving the cells it lit
+    /// and the node it reached, if it didn't leave the contraption first
+    fn trace(
+        map: &HashMap<Location, ContraptionSegment>,
+        nodes: &HashMap<BeamTip, usize>,
+        ncols: isize,
+        mut tip: BeamTip,
+    ) -> (Vec<usize>, Option<usize>) {
+        let mut cells = Vec::new();
+        while let Some(object) = map.get(&tip.location) {
+            if *object != ContraptionSegment::Empty {
+                return (cells, Some(nodes[&tip]));
+            }
+            cells.push(cell_index(ncols, &tip.location));
+            tip = tip.continue_in_line();
+        }
+        (cells, None)
+    }
+
+    /// Number of tiles energized by a beam entering at `start`
+    fn count_energized(&self, contraption: &Contraption, start: BeamTip) -> usize {
+        let (cells, node) = Self::trace(&contraption.map, &self.nodes, self.ncols, start);
+        let mut lit = match node {
+            Some(n) => self.reach[self.component_of[n]].clone(),
+            None => vec![0u64; self.reach.first().map_or(1, Vec::len)],
+        };
+        for cell in cells {
+            lit[cell / 64] |= 1 << (cell % 64);
+        }
+        lit.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+fn cell_index(ncols: isize, loc: &Location) -> usize {
+    (loc.row * ncols + loc.col) as usize
 }
 
-fn part2(text: &str) {
-    let nrows = text.lines().count() as isize;
-    let ncols = text.lines().next().unwrap().chars().count() as isize;
-    let mut best = 0;
-
-    // top and bottom
-    for col in 0..ncols {
-        // top
-        let mut contraption = ContraptionState::from_input(text);
-        contraption.set_starting_beam(BeamTip::new(Location::new(0, col), Direction::Down));
-        contraption.run_beams();
-        best = max(best, contraption.count_energized());
-
-        // bottom
-        let mut contraption = ContraptionState::from_input(text);
-        contraption.set_starting_beam(BeamTip::new(Location::new(ncols - 1, col), Direction::Up));
-        contraption.run_beams();
-        best = max(best, contraption.count_energized());
+/// Tarjan's algorithm, run iteratively so long beam paths can't overflow the
+/// stack. Components are returned in reverse topological order: every edge
+/// leaving a component goes to one listed before it.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next_index = 0;
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+
+        // (node, position in its edge list)
+        let mut work = vec![(root, 0)];
+        index[root] = next_index;
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, edge)) = work.pop() {
+            if let Some(next) = edges[node].get(edge).copied() {
+                work.push((node, edge + 1));
+                if index[next] == usize::MAX {
+                    index[next] = next_index;
+                    low[next] = next_index;
+                    next_index += 1;
+                    stack.push(next);
+                    on_stack[next] = true;
+                    work.push((next, 0));
+                } else if on_stack[next] {
+                    low[node] = low[node].min(index[next]);
+                }
+                continue;
+            }
+
+            // all edges done, pass the low link up and close the component
+            if let Some((parent, _)) = work.last() {
+                low[*parent] = low[*parent].min(low[node]);
+            }
+            if low[node] == index[node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
     }
 
-    // left and right
-    for row in 0..nrows {
-        // left
-        let mut contraption = ContraptionState::from_input(text);
-        contraption.set_starting_beam(BeamTip::new(Location::new(row, 0), Direction::Right));
-        contraption.run_beams();
-        best = max(best, contraption.count_energized());
-
-        // right
-        let mut contraption = ContraptionState::from_input(text);
-        contraption.set_starting_beam(BeamTip::new(Location::new(row, ncols - 1), Direction::Left));
-        contraption.run_beams();
-        best = max(best, contraption.count_energized());
+    components
+}
+
+fn show(text: &str) {
+    let contraption = Contraption::from_input(text);
+    let mut state = ContraptionState::new(
+        &contraption,
+        BeamTip::new(Location::new(0, 0), Direction::Right),
+    );
+    state.run_beams();
+    println!("{}", state);
+    println!("{}", state.render_energized());
+}
+
+/// Redraws the contraption after every beam step, only sensible for small
+/// inputs
+fn animate(text: &str, delay: Duration) {
+    let contraption = Contraption::from_input(text);
+    let mut state = ContraptionState::new(
+        &contraption,
+        BeamTip::new(Location::new(0, 0), Direction::Right),
+    );
+    let mut steps = 0;
+    while state.has_beams() {
+        state.run_single_beam();
+        steps += 1;
+        // clear the screen and move the cursor back to the top left
+        print!("\x1b[2J\x1b[H{}", state);
+        println!("Step {}, {} beams in flight", steps, state.beams.len());
+        thread::sleep(delay);
     }
+}
+
+fn part1(text: &str) {
+    let contraption = Contraption::from_input(text);
+    let mut state = ContraptionState::new(
+        &contraption,
+        BeamTip::new(Location::new(0, 0), Direction::Right),
+    );
+    state.run_beams();
+    println!("{}", state.count_energized());
+}
 
-    println!("{}", best);
+/// With `simulate`, every beam is run in full rather than trusting the graph
+fn part2(text: &str, simulate: bool) {
+    let contraption = Contraption::from_input(text);
+    let (start, energized) = if simulate {
+        contraption.best_start(|start| {
+            let mut state = ContraptionState::new(&contraption, start);
+            state.run_beams();
+            state.count_energized()
+        })
+    } else {
+        let graph = BeamGraph::new(&contraption);
+        contraption.best_start(|start| graph.count_energized(&contraption, start))
+    };
+
+    println!("{}", energized);
+    println!(
+        "Entering at row {}, col {} heading {:?}",
+        start.location.row, start.location.col, start.facing
+    );
 }
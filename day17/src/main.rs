@@ -1,425 +1,525 @@
-use petgraph::{algo::dijkstra, graph::NodeIndex, Graph};
+use std::fmt;
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        if cmd == "solve" {
+            solve(text, &args[1..]);
+            return;
+        }
+        if cmd == "routes" {
+            show_routes(text);
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum Direction {
     Up,
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
-struct NodeLayer(Vec<Vec<NodeIndex>>);
+impl Direction {
+    const ORTHOGONAL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
 
-impl NodeLayer {
-    fn make_node_layer(map: &mut Graph<i32, i32>, input: &str) -> Self {
-        Self(
-            input
-                .lines()
-                .map(|line| {
-                    line.chars()
-                        .map(|c| map.add_node(c.to_digit(10).unwrap() as i32))
-                        .collect()
-                })
-                .collect(),
-        )
+    const ALL: [Direction; 8] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
+
+    fn reverse(&self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
+        }
     }
 
-    fn lookup_node(&self, row: usize, col: usize) -> Option<&NodeIndex> {
-        self.0.get(row).and_then(|r| r.get(col))
+    /// Change in (row, col) for one block of movement
+    fn offset(&self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (-1, 1),
+            Direction::DownLeft => (1, -1),
+            Direction::DownRight => (1, 1),
+        }
     }
 
-    fn lookup_adjacent(&self, row: usize, col: usize, dir: Direction) -> Option<&NodeIndex> {
-        match dir {
-            Direction::Up => {
-                if let Some(row) = row.checked_sub(1) {
-                    self.lookup_node(row, col)
-                } else {
-                    None
-                }
-            }
-            Direction::Down => self.lookup_node(row + 1, col),
-            Direction::Left => {
-                if let Some(col) = col.checked_sub(1) {
-                    self.lookup_node(row, col)
-                } else {
-                    None
-                }
-            }
-            Direction::Right => self.lookup_node(row, col + 1),
+    fn arrow(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+            Direction::UpLeft => '↖',
+            Direction::UpRight => '↗',
+            Direction::DownLeft => '↙',
+            Direction::DownRight => '↘',
+        }
+    }
+}
+
+/// Which ways a crucible may leave a block other than straight on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TurnPolicy {
+    /// Left or right only, as in the puzzle
+    TurnsOnly,
+    /// Left, right, or straight back the way it came
+    AllowReversal,
+    /// Any of the eight directions except straight back
+    Diagonal,
+}
+
+impl TurnPolicy {
+    fn from_str(policy: &str) -> Self {
+        match policy {
+            "turns" => Self::TurnsOnly,
+            "reverse" => Self::AllowReversal,
+            "diagonal" => Self::Diagonal,
+            _ => panic!("Unexpected turn policy {policy}, expected turns, reverse or diagonal"),
+        }
+    }
+
+    fn directions(&self) -> &'static [Direction] {
+        match self {
+            TurnPolicy::TurnsOnly | TurnPolicy::AllowReversal => &Direction::ORTHOGONAL,
+            TurnPolicy::Diagonal => &Direction::ALL,
+        }
+    }
+
+    fn can_move(&self, facing: Direction, dir: Direction) -> bool {
+        match self {
+            TurnPolicy::TurnsOnly | TurnPolicy::Diagonal => dir != facing.reverse(),
+            TurnPolicy::AllowReversal => true,
         }
     }
 }
 
+/// How a kind of crucible is allowed to move: at most `max_run` blocks in a
+/// straight line, and at least `min_run` before it can turn or stop
+#[derive(Clone, Copy, Debug)]
+struct CrucibleRules {
+    min_run: usize,
+    max_run: usize,
+    turns: TurnPolicy,
+}
+
+static CRUCIBLE: CrucibleRules = CrucibleRules {
+    min_run: 1,
+    max_run: 3,
+    turns: TurnPolicy::TurnsOnly,
+};
+
+static ULTRA_CRUCIBLE: CrucibleRules = CrucibleRules {
+    min_run: 4,
+    max_run: 10,
+    turns: TurnPolicy::TurnsOnly,
+};
+
+/// Where a crucible is, which way it last moved, and how many blocks in a row
+/// it has moved that way
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Crucible {
+    row: usize,
+    col: usize,
+    facing: Direction,
+    run: usize,
+}
+
 struct Map {
-    map: Graph<i32, i32>,
-    up_nodes: Vec<NodeLayer>,
-    down_nodes: Vec<NodeLayer>,
-    left_nodes: Vec<NodeLayer>,
-    right_nodes: Vec<NodeLayer>,
-    start: NodeIndex,
-    end: NodeIndex,
-    nlayers: usize,
+    heat_loss: Vec<u32>,
     nrows: usize,
     ncols: usize,
 }
 
 impl Map {
-    fn with_layers(layers: usize, input: &str) -> Self {
-        let mut map = Graph::new();
-        let up_nodes = (0..layers)
-            .map(|_| NodeLayer::make_node_layer(&mut map, input))
-            .collect();
-        let down_nodes = (0..layers)
-            .map(|_| NodeLayer::make_node_layer(&mut map, input))
-            .collect();
-        let left_nodes = (0..layers)
-            .map(|_| NodeLayer::make_node_layer(&mut map, input))
-            .collect();
-        let right_nodes = (0..layers)
-            .map(|_| NodeLayer::make_node_layer(&mut map, input))
-            .collect();
-        let start = map.add_node(
-            input
-                .lines()
-                .next()
-                .unwrap()
-                .chars()
-                .next()
-                .unwrap()
-                .to_digit(10)
-                .unwrap() as i32,
-        );
-        let end = map.add_node(0);
+    fn from_input(input: &str) -> Self {
         Self {
-            map,
-            up_nodes,
-            down_nodes,
-            left_nodes,
-            right_nodes,
-            start,
-            end,
-            nlayers: layers,
+            heat_loss: input
+                .lines()
+                .flat_map(|line| line.chars().map(|c| c.to_digit(10).unwrap()))
+                .collect(),
             nrows: input.lines().count(),
             ncols: input.lines().next().unwrap().chars().count(),
         }
     }
 
-    fn add_edge(
-        &mut self,
-        start_idx: NodeIndex,
-        end_idx: NodeIndex,
-    ) -> petgraph::prelude::EdgeIndex {
-        self.map.add_edge(start_idx, end_idx, self.map[end_idx])
+    fn top_left(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    fn bottom_right(&self) -> (usize, usize) {
+        (self.nrows - 1, self.ncols - 1)
     }
 
-    fn construct_part1_edges(&mut self) {
-        use Direction as Dir;
-        assert_eq!(self.nlayers, PART1_LAYERS);
-
-        // special case, start node isn't in any layers
-        {
-            let down_node = *self.down_nodes[0].lookup_adjacent(0, 0, Dir::Down).unwrap();
-            let right_node = *self.right_nodes[0]
-                .lookup_adjacent(0, 0, Dir::Right)
-                .unwrap();
-            self.add_edge(self.start, down_node);
-            self.add_edge(self.start, right_node);
+    fn lookup_adjacent(&self, row: usize, col: usize, dir: Direction) -> Option<(usize, usize)> {
+        let (drow, dcol) = dir.offset();
+        let row = row
+            .checked_add_signed(drow)
+            .filter(|row| *row < self.nrows)?;
+        let col = col
+            .checked_add_signed(dcol)
+            .filter(|col| *col < self.ncols)?;
+        Some((row, col))
+    }
+
+    /// Slot for a crucible state in the flat cost table
+    fn state_index(&self, crucible: &Crucible, max_run: usize) -> usize {
+        ((crucible.row * self.ncols + crucible.col) * Direction::ALL.len()
+            + crucible.facing as usize)
+            * (max_run + 1)
+            + crucible.run
+    }
+
+    fn state_at(&self, slot: usize, max_run: usize) -> Crucible {
+        let run = slot % (max_run + 1);
+        let rest = slot / (max_run + 1);
+        let cell = rest / Direction::ALL.len();
+        Crucible {
+            row: cell / self.ncols,
+            col: cell % self.ncols,
+            facing: Direction::ALL[rest % Direction::ALL.len()],
+            run,
         }
+    }
 
-        // special case, end node isn't in any layers
-        for layer in 0..self.nlayers {
-            self.add_edge(
-                *self.up_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-            self.add_edge(
-                *self.down_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-            self.add_edge(
-                *self.left_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-            self.add_edge(
-                *self.right_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
+    /// Cheapest route from `start` to `end` while following `rules`, or `None`
+    /// if the crucible can't get there.
+    ///
+    /// A* search over crucible states, generated as they're reached rather
+    /// than built up front. Every block loses at least the smallest digit on
+    /// the map, so that times the number of moves left never overestimates.
+    /// Estimates are small integers, so the queue is a bucket per estimate.
+    fn find_route(
+        &self,
+        rules: &CrucibleRules,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Option<Route> {
+        // the cost table has a slot per run length up to `max_run`, and every
+        // move starts a run of at least 1
+        assert!(
+            rules.max_run >= 1 && rules.min_run <= rules.max_run,
+            "Runs of {} to {} blocks aren't possible",
+            rules.min_run,
+            rules.max_run
+        );
+        for (name, (row, col)) in [("Start", start), ("End", end)] {
+            assert!(
+                row < self.nrows && col < self.ncols,
+                "{} {:?} is outside the {}x{} map",
+                name,
+                (row, col),
+                self.nrows,
+                self.ncols
             );
         }
+        // a crucible that only has to move one block before stopping can just
+        // not move, but longer minimum runs have to come back round
+        if start == end && rules.min_run <= 1 {
+            return Some(Route {
+                start,
+                steps: Vec::new(),
+                cost: 0,
+            });
+        }
 
-        // handle nodes going up
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.up_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(lnode) = self.left_nodes[0].lookup_adjacent(row, col, Dir::Left) {
-                        self.add_edge(start, *lnode);
-                    }
-                    if let Some(rnode) = self.right_nodes[0].lookup_adjacent(row, col, Dir::Right) {
-                        self.add_edge(start, *rnode);
-                    }
-                    if let Some(unode) = self
-                        .up_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Up))
-                    {
-                        self.add_edge(start, *unode);
-                    }
-                }
+        let min_loss = *self.heat_loss.iter().min().unwrap();
+        let estimate = |row: usize, col: usize| {
+            let (drow, dcol) = (row.abs_diff(end.0), col.abs_diff(end.1));
+            let moves = match rules.turns {
+                TurnPolicy::Diagonal => drow.max(dcol),
+                TurnPolicy::TurnsOnly | TurnPolicy::AllowReversal => drow + dcol,
+            };
+            moves as u32 * min_loss
+        };
+
+        let mut best =
+            vec![u32::MAX; self.nrows * self.ncols * Direction::ALL.len() * (rules.max_run + 1)];
+        // slot each state was reached from, `usize::MAX` for the first moves
+        let mut came_from = vec![usize::MAX; best.len()];
+        let mut queue = BucketQueue::default();
+
+        // the starting block has no direction, so seed the first moves
+        for dir in rules.turns.directions() {
+            if let Some((row, col)) = self.lookup_adjacent(start.0, start.1, *dir) {
+                let crucible = Crucible {
+                    row,
+                    col,
+                    facing: *dir,
+                    run: 1,
+                };
+                let cost = self.heat_loss[row * self.ncols + col];
+                let slot = self.state_index(&crucible, rules.max_run);
+                best[slot] = cost;
+                queue.push(cost + estimate(row, col), slot);
             }
         }
 
-        // handle nodes going down
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.down_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(lnode) = self.left_nodes[0].lookup_adjacent(row, col, Dir::Left) {
-                        self.add_edge(start, *lnode);
-                    }
-                    if let Some(rnode) = self.right_nodes[0].lookup_adjacent(row, col, Dir::Right) {
-                        self.add_edge(start, *rnode);
-                    }
-                    if let Some(dnode) = self
-                        .down_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Down))
-                    {
-                        self.add_edge(start, *dnode);
-                    }
-                }
+        while let Some((estimated, slot)) = queue.pop() {
+            let crucible = self.state_at(slot, rules.max_run);
+            let cost = best[slot];
+            if estimated > cost + estimate(crucible.row, crucible.col) {
+                continue; // already reached more cheaply
+            }
+            if (crucible.row, crucible.col) == end && crucible.run >= rules.min_run {
+                return Some(self.trace_route(start, slot, &best, &came_from, rules.max_run));
             }
-        }
 
-        // handle nodes going left
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.left_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(unode) = self.up_nodes[0].lookup_adjacent(row, col, Dir::Up) {
-                        self.add_edge(start, *unode);
-                    }
-                    if let Some(dnode) = self.down_nodes[0].lookup_adjacent(row, col, Dir::Down) {
-                        self.add_edge(start, *dnode);
-                    }
-                    if let Some(lnode) = self
-                        .left_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Left))
-                    {
-                        self.add_edge(start, *lnode);
-                    }
+            for dir in rules.turns.directions() {
+                let dir = *dir;
+                let run = if dir == crucible.facing {
+                    crucible.run + 1
+                } else {
+                    1
+                };
+                if !rules.turns.can_move(crucible.facing, dir)
+                    || run > rules.max_run
+                    || (dir != crucible.facing && crucible.run < rules.min_run)
+                {
+                    continue;
                 }
-            }
-        }
 
-        // handle nodes going right
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.right_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(unode) = self.up_nodes[0].lookup_adjacent(row, col, Dir::Up) {
-                        self.add_edge(start, *unode);
-                    }
-                    if let Some(dnode) = self.down_nodes[0].lookup_adjacent(row, col, Dir::Down) {
-                        self.add_edge(start, *dnode);
-                    }
-                    if let Some(rnode) = self
-                        .right_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Right))
-                    {
-                        self.add_edge(start, *rnode);
-                    }
+                let Some((row, col)) = self.lookup_adjacent(crucible.row, crucible.col, dir) else {
+                    continue;
+                };
+                let next = Crucible {
+                    row,
+                    col,
+                    facing: dir,
+                    run,
+                };
+                let next_cost = cost + self.heat_loss[row * self.ncols + col];
+                let next_slot = self.state_index(&next, rules.max_run);
+                if next_cost < best[next_slot] {
+                    best[next_slot] = next_cost;
+                    came_from[next_slot] = slot;
+                    queue.push(next_cost + estimate(row, col), next_slot);
                 }
             }
         }
+
+        None
     }
 
-    fn construct_part2_edges(&mut self) {
-        use Direction as Dir;
-        assert_eq!(self.nlayers, PART2_LAYERS);
-
-        // special case, start node isn't in any layers
-        {
-            let down_node = *self.down_nodes[0].lookup_adjacent(0, 0, Dir::Down).unwrap();
-            let right_node = *self.right_nodes[0]
-                .lookup_adjacent(0, 0, Dir::Right)
-                .unwrap();
-            self.add_edge(self.start, down_node);
-            self.add_edge(self.start, right_node);
+    /// Follows `came_from` back from the state at `slot` to the start
+    fn trace_route(
+        &self,
+        start: (usize, usize),
+        mut slot: usize,
+        best: &[u32],
+        came_from: &[usize],
+        max_run: usize,
+    ) -> Route {
+        let cost = best[slot];
+        let mut steps = Vec::new();
+        while slot != usize::MAX {
+            let crucible = self.state_at(slot, max_run);
+            steps.push(((crucible.row, crucible.col), crucible.facing));
+            slot = came_from[slot];
         }
+        steps.reverse();
 
-        // special case, end node isn't in any layers
-        for layer in PART2_CANT_TURN..self.nlayers {
-            self.add_edge(
-                *self.up_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-            self.add_edge(
-                *self.down_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-            self.add_edge(
-                *self.left_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-            self.add_edge(
-                *self.right_nodes[layer]
-                    .lookup_node(self.nrows - 1, self.ncols - 1)
-                    .unwrap(),
-                self.end,
-            );
-        }
+        Route { start, steps, cost }
+    }
 
-        // handle nodes going up
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.up_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(unode) = self
-                        .up_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Up))
-                    {
-                        self.add_edge(start, *unode);
-                    }
-                    if layer >= PART2_CANT_TURN {
-                        if let Some(lnode) = self.left_nodes[0].lookup_adjacent(row, col, Dir::Left)
-                        {
-                            self.add_edge(start, *lnode);
-                        }
-                        if let Some(rnode) =
-                            self.right_nodes[0].lookup_adjacent(row, col, Dir::Right)
-                        {
-                            self.add_edge(start, *rnode);
-                        }
-                    }
-                }
-            }
+    /// The map with the route drawn over it, each block the route enters
+    /// marked with the direction it entered in
+    fn render<'a>(&'a self, route: &'a Route) -> RouteView<'a> {
+        RouteView { map: self, route }
+    }
+}
+
+/// A path the crucible takes through the map
+#[derive(Clone, Debug)]
+struct Route {
+    start: (usize, usize),
+    /// Each block entered, with the direction the crucible moved to enter it
+    steps: Vec<((usize, usize), Direction)>,
+    cost: u32,
+}
+
+impl Route {
+    /// Blocks where the direction of travel changes
+    fn turns(&self) -> usize {
+        self.steps.windows(2).filter(|w| w[0].1 != w[1].1).count()
+    }
+
+    /// Lengths of each straight run of blocks, in order
+    fn runs(&self) -> Vec<usize> {
+        self.steps
+            .chunk_by(|a, b| a.1 == b.1)
+            .map(|run| run.len())
+            .collect()
+    }
+}
+
+struct RouteView<'a> {
+    map: &'a Map,
+    route: &'a Route,
+}
+
+impl fmt::Display for RouteView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut grid: Vec<char> = self
+            .map
+            .heat_loss
+            .iter()
+            .map(|loss| char::from_digit(*loss, 10).unwrap())
+            .collect();
+        for ((row, col), dir) in &self.route.steps {
+            grid[row * self.map.ncols + col] = dir.arrow();
         }
+        // drawn last so a route that loops back to the start doesn't hide it
+        grid[self.route.start.0 * self.map.ncols + self.route.start.1] = 'S';
 
-        // handle nodes going down
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.down_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(dnode) = self
-                        .down_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Down))
-                    {
-                        self.add_edge(start, *dnode);
-                    }
-                    if layer >= PART2_CANT_TURN {
-                        if let Some(lnode) = self.left_nodes[0].lookup_adjacent(row, col, Dir::Left)
-                        {
-                            self.add_edge(start, *lnode);
-                        }
-                        if let Some(rnode) =
-                            self.right_nodes[0].lookup_adjacent(row, col, Dir::Right)
-                        {
-                            self.add_edge(start, *rnode);
-                        }
-                    }
-                }
-            }
+        for row in grid.chunks(self.map.ncols) {
+            writeln!(f, "{}", row.iter().collect::<String>())?;
         }
+        Ok(())
+    }
+}
 
-        // handle nodes going left
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.left_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(lnode) = self
-                        .left_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Left))
-                    {
-                        self.add_edge(start, *lnode);
-                    }
-                    if layer >= PART2_CANT_TURN {
-                        if let Some(unode) = self.up_nodes[0].lookup_adjacent(row, col, Dir::Up) {
-                            self.add_edge(start, *unode);
-                        }
-                        if let Some(dnode) = self.down_nodes[0].lookup_adjacent(row, col, Dir::Down)
-                        {
-                            self.add_edge(start, *dnode);
-                        }
-                    }
-                }
-            }
+/// Priority queue for small integer priorities, popping the lowest first
+#[derive(Default)]
+struct BucketQueue {
+    buckets: Vec<Vec<usize>>,
+    lowest: usize,
+}
+
+impl BucketQueue {
+    fn push(&mut self, priority: u32, item: usize) {
+        let priority = priority as usize;
+        if priority >= self.buckets.len() {
+            self.buckets.resize_with(priority + 1, Vec::new);
         }
+        self.lowest = self.lowest.min(priority);
+        self.buckets[priority].push(item);
+    }
 
-        // handle nodes going right
-        for layer in 0..self.nlayers {
-            for row in 0..self.nrows {
-                for col in 0..self.ncols {
-                    let start = *self.right_nodes[layer].lookup_node(row, col).unwrap();
-                    if let Some(rnode) = self
-                        .right_nodes
-                        .get(layer + 1)
-                        .and_then(|layer| layer.lookup_adjacent(row, col, Dir::Right))
-                    {
-                        self.add_edge(start, *rnode);
-                    }
-                    if layer >= PART2_CANT_TURN {
-                        if let Some(unode) = self.up_nodes[0].lookup_adjacent(row, col, Dir::Up) {
-                            self.add_edge(start, *unode);
-                        }
-                        if let Some(dnode) = self.down_nodes[0].lookup_adjacent(row, col, Dir::Down)
-                        {
-                            self.add_edge(start, *dnode);
-                        }
-                    }
-                }
+    fn pop(&mut self) -> Option<(u32, usize)> {
+        while self.lowest < self.buckets.len() {
+            if let Some(item) = self.buckets[self.lowest].pop() {
+                return Some((self.lowest as u32, item));
             }
+            self.lowest += 1;
         }
+        None
     }
+}
+
+/// Runs the solver with rules and end points given on the command line:
+/// `MIN_RUN MAX_RUN POLICY [START_ROW START_COL END_ROW END_COL]`
+fn solve(text: &str, args: &[String]) {
+    let number = |n: usize| -> usize {
+        args.get(n)
+            .unwrap_or_else(|| panic!("Missing argument {}", n + 1))
+            .parse()
+            .expect("Invalid number")
+    };
+    let rules = CrucibleRules {
+        min_run: number(0),
+        max_run: number(1),
+        turns: TurnPolicy::from_str(args.get(2).expect("Missing turn policy")),
+    };
 
-    fn find_cost(&self) -> i32 {
-        *dijkstra(&self.map, self.start, Some(self.end), |edge| *edge.weight())
-            .get(&self.end)
-            .unwrap()
+    let map = Map::from_input(text);
+    let (start, end) = if args.len() > 3 {
+        ((number(3), number(4)), (number(5), number(6)))
+    } else {
+        (map.top_left(), map.bottom_right())
+    };
+
+    match map.find_route(&rules, start, end) {
+        Some(route) => print!("{}\nHeat loss: {}\n", map.render(&route), route.cost),
+        None => println!("No route from {:?} to {:?}", start, end),
     }
 }
 
-static PART1_LAYERS: usize = 3;
+/// Draws the part 1 and part 2 routes, along with how their shapes compare
+fn show_routes(text: &str) {
+    let map = Map::from_input(text);
+    let routes =
+        [("Crucible", &CRUCIBLE), ("Ultra crucible", &ULTRA_CRUCIBLE)].map(|(name, rules)| {
+            let route = map.find_route(rules, map.top_left(), map.bottom_right());
+            (name, route)
+        });
 
-fn part1(text: &str) {
-    let mut map = Map::with_layers(PART1_LAYERS, text);
-    map.construct_part1_edges();
-    println!("{}", map.find_cost());
+    for (name, route) in &routes {
+        let Some(route) = route else {
+            println!("{name}:\nNo route\n");
+            continue;
+        };
+        println!("{name}:\n{}", map.render(route));
+        let runs = route.runs();
+        println!(
+            "Heat loss {}, {} blocks, {} turns, runs of {} to {} blocks\n",
+            route.cost,
+            route.steps.len(),
+            route.turns(),
+            runs.iter().min().unwrap(),
+            runs.iter().max().unwrap(),
+        );
+    }
+
+    let [(_, Some(first)), (_, Some(second))] = &routes else {
+        return;
+    };
+    let shared = first
+        .steps
+        .iter()
+        .filter(|(cell, _)| second.steps.iter().any(|(other, _)| other == cell))
+        .count();
+    println!("Blocks on both routes: {shared}");
 }
 
-static PART2_LAYERS: usize = 10;
-static PART2_CANT_TURN: usize = 3;
+fn part1(text: &str) {
+    let map = Map::from_input(text);
+    match map.find_route(&CRUCIBLE, map.top_left(), map.bottom_right()) {
+        Some(route) => println!("{}", route.cost),
+        None => println!("No route"),
+    }
+}
 
 fn part2(text: &str) {
-    let mut map = Map::with_layers(PART2_LAYERS, text);
-    map.construct_part2_edges();
-    println!("{}", map.find_cost());
+    let map = Map::from_input(text);
+    match map.find_route(&ULTRA_CRUCIBLE, map.top_left(), map.bottom_right()) {
+        Some(route) => println!("{}", route.cost),
+        None => println!("No route"),
+    }
 }
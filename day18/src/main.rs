@@ -1,15 +1,34 @@
-use std::{str::FromStr, string::ParseError};
+use std::{fmt, fs, str::FromStr, string::ParseError};
 
 use itertools::Itertools;
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        // a trailing `color` runs the plan hidden in the colour codes instead
+        let mut pool = VertexMap::new();
+        if args.last().is_some_and(|arg| arg == "color") {
+            pool.run_input_using_color(text);
+        } else {
+            pool.run_input(text);
+        }
+
+        match cmd.as_str() {
+            "check" => check_plan(&pool),
+            "show" => show_lagoon(&pool, None),
+            "ppm" => show_lagoon(&pool, Some(args.get(1).expect("Missing image path"))),
+            _ => panic!("Unknown command {cmd}, expected check, show or ppm"),
+        }
+        return;
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
@@ -40,9 +59,15 @@ impl From<(isize, isize)> for Location {
     }
 }
 
-impl Into<(isize, isize)> for Location {
-    fn into(self) -> (isize, isize) {
-        (self.row, self.col)
+impl From<Location> for (isize, isize) {
+    fn from(loc: Location) -> Self {
+        (loc.row, loc.col)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.row, self.col)
     }
 }
 
@@ -64,6 +89,15 @@ impl Direction {
             _ => panic!("Invalid char {c} in Direction::from_char()"),
         }
     }
+
+    fn reverse(&self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
 }
 
 struct Step {
@@ -79,12 +113,7 @@ impl FromStr for Step {
         let mut iter = s.split_whitespace();
         let dir = Direction::from_char(&iter.next().and_then(|s| s.chars().next()).unwrap());
         let dist = iter.next().and_then(|d| d.parse().ok()).unwrap();
-        let color = iter
-            .next()
-            .unwrap()
-            .replace("(", "")
-            .replace("#", "")
-            .replace(")", "");
+        let color = iter.next().unwrap().replace(['(', '#', ')'], "");
         Ok(Self {
             dir,
             distance: dist,
@@ -94,7 +123,13 @@ impl FromStr for Step {
 }
 
 impl Step {
-    fn from_color(&self) -> Step {
+    /// The colour code as red, green and blue
+    fn rgb(&self) -> Rgb {
+        let channel = |n: usize| u8::from_str_radix(&self.color[2 * n..2 * n + 2], 16).unwrap();
+        Rgb(channel(0), channel(1), channel(2))
+    }
+
+    fn decoded_from_color(&self) -> Step {
         let mut color_chars = self.color.chars();
         let mut dist = 0;
         for _ in 0..5 {
@@ -104,55 +139,119 @@ impl Step {
             '0' => Direction::Right,
             '1' => Direction::Down,
             '2' => Direction::Left,
-            '3' => Direction::Right,
+            '3' => Direction::Up,
             _ => panic!("Unexpected char in from_color"),
         };
-        assert!(color_chars.next() == None); // should have finished it
+        assert!(color_chars.next().is_none()); // should have finished it
 
         Step {
             dir,
             distance: dist as usize,
-            color: "".to_string(),
+            color: self.color.clone(),
         }
     }
 }
 
-struct VertexMap {
-    vertices: Vec<Location>,
-    location: Location,
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Rgb(u8, u8, u8);
+
+/// A straight stretch of trench between two corners of the plan
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    from: Location,
+    to: Location,
+    dir: Direction,
+    color: Rgb,
 }
 
-fn normalize(n: isize) -> isize {
-    if n == 0 {
-        0
-    } else {
-        n / n.abs()
+impl Segment {
+    fn rows(&self) -> (isize, isize) {
+        (
+            self.from.row.min(self.to.row),
+            self.from.row.max(self.to.row),
+        )
+    }
+
+    fn cols(&self) -> (isize, isize) {
+        (
+            self.from.col.min(self.to.col),
+            self.from.col.max(self.to.col),
+        )
+    }
+
+    /// Whether the two segments share any cell. Both are axis aligned, so this
+    /// is just whether their bounding boxes overlap.
+    fn touches(&self, other: &Segment) -> bool {
+        let overlaps = |(a_lo, a_hi): (isize, isize), (b_lo, b_hi): (isize, isize)| {
+            a_lo <= b_hi && b_lo <= a_hi
+        };
+        overlaps(self.rows(), other.rows()) && overlaps(self.cols(), other.cols())
     }
 }
 
+#[derive(Debug)]
+enum PlanError {
+    /// The last step finishes somewhere other than the start
+    NotClosed(Location),
+    /// Two steps of the plan dig through the same cell, other than the corner
+    /// between consecutive steps. Holds the step numbers, counting from 1.
+    SelfIntersection(usize, usize),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::NotClosed(loc) => {
+                write!(f, "plan ends at ({}) instead of back at the start", loc)
+            }
+            PlanError::SelfIntersection(first, second) => {
+                write!(
+                    f,
+                    "step {} crosses the trench dug by step {}",
+                    second, first
+                )
+            }
+        }
+    }
+}
+
+struct VertexMap {
+    /// Each corner of the trench, starting and ending at the origin
+    vertices: Vec<Location>,
+    /// Step number of the step that dug to each vertex after the first
+    step_numbers: Vec<usize>,
+    /// Colour of the step that dug to each vertex after the first
+    colors: Vec<Rgb>,
+    location: Location,
+}
+
 impl VertexMap {
     fn new() -> Self {
         Self {
             vertices: vec![Location::new(0, 0)],
+            step_numbers: Vec::new(),
+            colors: Vec::new(),
             location: Location::new(0, 0),
         }
     }
 
     fn run_step(&mut self, step: &Step) {
+        let number = self.step_numbers.len() + 1;
         let vertex = self.location.cell_in_dir(step.dir, step.distance);
         self.vertices.push(vertex);
+        self.step_numbers.push(number);
+        self.colors.push(step.rgb());
         self.location = vertex;
     }
 
     fn run_step_using_color(&mut self, step: &Step) {
-        self.run_step(&step.from_color());
+        self.run_step(&step.decoded_from_color());
     }
 
     fn run_input(&mut self, input: &str) {
         for step in input.lines().map(|line| line.parse().unwrap()) {
             self.run_step(&step);
         }
-        self.vertices.pop(); //last vertex is adjacent to the first one, just complicates things
     }
 
     fn run_input_using_color(&mut self, input: &str) {
@@ -161,63 +260,299 @@ impl VertexMap {
         }
     }
 
-    fn calculate_area(&self) -> f64 {
-        let true_vertices = self
-            .vertices
+    /// Stretches of trench between corners, leaving out steps of no distance.
+    /// Each is paired with the number of the step that dug it.
+    fn segments(&self) -> Vec<(usize, Segment)> {
+        self.vertices
             .iter()
-            .circular_tuple_windows()
-            .map(|(prev, current, next)| {
-                let base_row = current.row as f64;
-                let base_col = current.col as f64;
-
-                let rdiff1 = normalize(current.row - prev.row);
-                let cdiff1 = normalize(current.col - prev.col);
-                let rdiff2 = normalize(next.row - current.row);
-                let cdiff2 = normalize(next.col - current.col);
-
-                //println!("({base_row}, {base_col}): {rdiff1}, {cdiff1}, {rdiff2}, {cdiff2}");
-
-                match (rdiff1, cdiff1, rdiff2, cdiff2) {
-                    (0, 1, 1, 0) => (base_row - 0.5, base_col + 0.5),
-                    (0, -1, 1, 0) => (base_row - 0.5, base_col - 0.5),
-                    (0, 1, -1, 0) => (base_row + 0.5, base_col + 0.5),
-                    (0, -1, -1, 0) => (base_row + 0.5, base_col - 0.5),
-                    (1, 0, 0, 1) => (base_row + 0.5, base_col - 0.5),
-                    (-1, 0, 0, 1) => (base_row - 0.5, base_col - 0.5),
-                    (1, 0, 0, -1) => (base_row + 0.5, base_col + 0.5),
-                    (-1, 0, 0, -1) => (base_row - 0.5, base_col + 0.5),
-                    _ => panic!("Invalid differences: {rdiff1}, {cdiff1}, {rdiff2}, {cdiff2}"),
-                }
+            .tuple_windows()
+            .zip(self.step_numbers.iter().zip(&self.colors))
+            .filter(|((from, to), _)| from != to)
+            .map(|((from, to), (number, color))| {
+                let dir = match (to.row - from.row, to.col - from.col) {
+                    (r, 0) if r > 0 => Direction::Up,
+                    (r, 0) if r < 0 => Direction::Down,
+                    (0, c) if c < 0 => Direction::Left,
+                    _ => Direction::Right,
+                };
+                (
+                    *number,
+                    Segment {
+                        from: *from,
+                        to: *to,
+                        dir,
+                        color: *color,
+                    },
+                )
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
+
+    /// Checks the trench is a single closed loop that never crosses or runs
+    /// back over itself. Steps carrying on in the same direction are fine.
+    fn validate(&self) -> Result<Vec<Segment>, PlanError> {
+        let end = *self.vertices.last().unwrap();
+        if end != self.vertices[0] {
+            return Err(PlanError::NotClosed(end));
+        }
+
+        let segments = self.segments();
+        let n = segments.len();
+        for i in 0..n {
+            for j in i + 1..n {
+                let (first, a) = &segments[i];
+                let (second, b) = &segments[j];
+                let adjacent = j == i + 1 || (i == 0 && j == n - 1);
+                let crossed = if adjacent {
+                    // consecutive stretches share a corner, so only doubling
+                    // back counts
+                    a.dir == b.dir.reverse()
+                } else {
+                    a.touches(b)
+                };
+                if crossed {
+                    return Err(PlanError::SelfIntersection(*first, *second));
+                }
+            }
+        }
 
-        //println!("{:?}", true_vertices);
-        /*println!(
-            "{:?}",
-            self.vertices
+        Ok(segments.into_iter().map(|(_, segment)| segment).collect())
+    }
+
+    /// Cubic metres dug out, counting both the trench and the interior.
+    ///
+    /// The shoelace formula over the centres of the corner cells gives the area
+    /// `A` of the polygon they make, and Pick's theorem says that it encloses
+    /// `A - b/2 + 1` whole cells, where `b` is the number of trench cells on
+    /// its boundary. Adding the trench back gives `A + b/2 + 1`.
+    fn calculate_area(&self) -> Result<i128, PlanError> {
+        let segments = self.validate()?;
+
+        let (twice_area, perimeter) =
+            segments
                 .iter()
-                .map(|loc| (*loc).into())
-                .collect::<Vec<(isize, isize)>>()
-        );*/
+                .fold((0i128, 0i128), |(area, perimeter), segment| {
+                    let (from, to) = (segment.from, segment.to);
+                    let cross =
+                        from.row as i128 * to.col as i128 - to.row as i128 * from.col as i128;
+                    let length =
+                        (to.row - from.row).abs() as i128 + (to.col - from.col).abs() as i128;
+                    (area + cross, perimeter + length)
+                });
 
-        true_vertices
+        Ok((twice_area.abs() + perimeter) / 2 + 1)
+    }
+
+    /// Lowest and highest row and column the trench reaches
+    fn bounds(&self) -> (Location, Location) {
+        let (min_row, max_row) = self
+            .vertices
             .iter()
-            .circular_tuple_windows()
-            .fold(0.0, |acc, (current, prev)| {
-                acc + ((prev.0 + current.0) * (prev.1 - current.1))
+            .map(|v| v.row)
+            .minmax()
+            .into_option()
+            .unwrap();
+        let (min_col, max_col) = self
+            .vertices
+            .iter()
+            .map(|v| v.col)
+            .minmax()
+            .into_option()
+            .unwrap();
+        (
+            Location::new(min_row, min_col),
+            Location::new(max_row, max_col),
+        )
+    }
+
+    /// Digs the plan out into a grid of cells, or `None` if the grid would have
+    /// more than `max_cells` cells
+    fn rasterise(&self, max_cells: usize) -> Result<Option<Lagoon>, PlanError> {
+        let segments = self.validate()?;
+
+        let (low, high) = self.bounds();
+        let nrows = (high.row - low.row + 1) as usize;
+        let ncols = (high.col - low.col + 1) as usize;
+        if nrows.saturating_mul(ncols) > max_cells {
+            return Ok(None);
+        }
+
+        // rows count upwards, so the grid is filled from the top row down
+        let index =
+            |loc: Location| (high.row - loc.row) as usize * ncols + (loc.col - low.col) as usize;
+        let mut cells = vec![Cell::Outside; nrows * ncols];
+        for segment in &segments {
+            let (row_lo, row_hi) = segment.rows();
+            let (col_lo, col_hi) = segment.cols();
+            for row in row_lo..=row_hi {
+                for col in col_lo..=col_hi {
+                    cells[index(Location::new(row, col))] = Cell::Trench(segment.color);
+                }
+            }
+        }
+
+        // anything the outside can't reach around the edges is interior
+        let mut reached = vec![false; cells.len()];
+        let mut stack: Vec<usize> = (0..cells.len())
+            .filter(|n| {
+                let (row, col) = (n / ncols, n % ncols);
+                row == 0 || row == nrows - 1 || col == 0 || col == ncols - 1
             })
-            / 2.0
+            .filter(|n| cells[*n] == Cell::Outside)
+            .collect();
+        while let Some(n) = stack.pop() {
+            if reached[n] {
+                continue;
+            }
+            reached[n] = true;
+            let (row, col) = (n / ncols, n % ncols);
+            let neighbours = [
+                (row > 0).then(|| n - ncols),
+                (row + 1 < nrows).then(|| n + ncols),
+                (col > 0).then(|| n - 1),
+                (col + 1 < ncols).then(|| n + 1),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if !reached[next] && cells[next] == Cell::Outside {
+                    stack.push(next);
+                }
+            }
+        }
+        for (cell, reached) in cells.iter_mut().zip(reached) {
+            if *cell == Cell::Outside && !reached {
+                *cell = Cell::Interior;
+            }
+        }
+
+        Ok(Some(Lagoon {
+            cells,
+            nrows,
+            ncols,
+        }))
+    }
+}
+
+/// Plans with more cells than this are too big to draw
+static MAX_RASTER_CELLS: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Cell {
+    Outside,
+    Interior,
+    Trench(Rgb),
+}
+
+impl Cell {
+    fn rgb(&self) -> Rgb {
+        match self {
+            Cell::Outside => Rgb(0, 0, 0),
+            Cell::Interior => Rgb(96, 96, 96),
+            Cell::Trench(color) => *color,
+        }
+    }
+}
+
+/// The dug out lagoon, with the top row of the grid being the most northern
+struct Lagoon {
+    cells: Vec<Cell>,
+    nrows: usize,
+    ncols: usize,
+}
+
+impl Lagoon {
+    fn dug_out(&self) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| **cell != Cell::Outside)
+            .count()
+    }
+
+    /// Binary PPM image, one pixel per cell
+    fn to_ppm(&self) -> Vec<u8> {
+        let mut image = format!("P6\n{} {}\n255\n", self.ncols, self.nrows).into_bytes();
+        for cell in &self.cells {
+            let Rgb(r, g, b) = cell.rgb();
+            image.extend([r, g, b]);
+        }
+        image
+    }
+}
+
+/// Draws the trench in its own colours with ANSI truecolour escapes, and the
+/// interior as plain `#`
+impl fmt::Display for Lagoon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.cells.chunks(self.ncols) {
+            for cell in row {
+                match cell {
+                    Cell::Outside => write!(f, ".")?,
+                    Cell::Interior => write!(f, "#")?,
+                    Cell::Trench(Rgb(r, g, b)) => write!(f, "\x1b[38;2;{r};{g};{b}m#\x1b[0m")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
+fn check_plan(pool: &VertexMap) {
+    match pool.calculate_area() {
+        Ok(area) => println!(
+            "Plan is a closed loop of {} steps, digging out {}",
+            pool.step_numbers.len(),
+            area
+        ),
+        Err(e) => println!("Invalid dig plan: {}", e),
+    }
+}
+
+/// Draws the lagoon to the terminal, or to a PPM image at `path`
+fn show_lagoon(pool: &VertexMap, path: Option<&String>) {
+    let lagoon = match pool.rasterise(MAX_RASTER_CELLS) {
+        Ok(Some(lagoon)) => lagoon,
+        Ok(None) => {
+            let (low, high) = pool.bounds();
+            println!(
+                "Plan is too big to draw: {} by {}",
+                high.row - low.row + 1,
+                high.col - low.col + 1
+            );
+            return;
+        }
+        Err(e) => {
+            println!("Invalid dig plan: {}", e);
+            return;
+        }
+    };
+
+    match path {
+        Some(path) => {
+            fs::write(path, lagoon.to_ppm()).expect("Failed to write image");
+            println!(
+                "Wrote {} by {} image to {}",
+                lagoon.ncols, lagoon.nrows, path
+            );
+        }
+        None => print!("{}", lagoon),
+    }
+    println!("Dug out: {}", lagoon.dug_out());
+}
+
 fn part1(text: &str) {
     let mut pool = VertexMap::new();
     pool.run_input(text);
-    println!("{}", pool.calculate_area());
+    match pool.calculate_area() {
+        Ok(area) => println!("{}", area),
+        Err(e) => println!("Invalid dig plan: {}", e),
+    }
 }
 
 fn part2(text: &str) {
     let mut pool = VertexMap::new();
     pool.run_input_using_color(text);
-    println!("{}", pool.calculate_area());
+    match pool.calculate_area() {
+        Ok(area) => println!("{}", area),
+        Err(e) => println!("Invalid dig plan: {}", e),
+    }
 }
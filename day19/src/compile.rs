@@ -0,0 +1,390 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{Attributes, Condition, Part, PartRange, RuleSet};
+
+/// Where a part goes once a workflow is done with it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    Accept,
+    Reject,
+    Workflow(usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Test {
+    pub condition: Condition,
+    pub target: Target,
+}
+
+#[derive(Clone, Debug)]
+pub struct Workflow {
+    pub name: String,
+    pub tests: Vec<Test>,
+    pub default: Target,
+}
+
+#[derive(Debug)]
+pub enum CompileError {
+    /// There's no `in` workflow to start from
+    NoStart,
+    /// A rule sends parts to a workflow that doesn't exist
+    UnknownWorkflow { from: String, to: String },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::NoStart => write!(f, "no workflow named in"),
+            CompileError::UnknownWorkflow { from, to } => {
+                write!(f, "{} sends parts to unknown workflow {}", from, to)
+            }
+        }
+    }
+}
+
+/// Parts that really do go round a cycle of workflows, so never get accepted
+/// or rejected
+#[derive(Debug)]
+pub enum LoopError {
+    /// A single part, going round through the named workflow
+    Part(String),
+    /// Some box of parts, going round through the named workflow
+    Parts(String),
+}
+
+impl fmt::Display for LoopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopError::Part(name) => write!(f, "part loops forever through {}", name),
+            LoopError::Parts(name) => write!(f, "parts loop forever through {}", name),
+        }
+    }
+}
+
+/// Something in the workflows that can't be what was meant. Rules are
+/// numbered from 1 within their workflow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// No part can ever reach the workflow
+    UnreachableWorkflow(String),
+    /// Every part that gets to the workflow is sent on before this rule
+    UnreachableRule(String, usize, String),
+    /// Every part is sent on by an earlier rule, so the default is never used
+    UnreachableDefault(String),
+    /// Every part that gets to the rule passes it
+    AlwaysTrue(String, usize, String),
+    /// No part that gets to the rule passes it
+    AlwaysFalse(String, usize, String),
+    /// Workflows that can send parts round in a loop, in the order they're
+    /// visited
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnreachableWorkflow(name) => write!(f, "{} is never reached", name),
+            Warning::UnreachableRule(name, rule, condition) => {
+                write!(f, "{} rule {} ({}) is never reached", name, rule, condition)
+            }
+            Warning::UnreachableDefault(name) => {
+                write!(f, "{} never falls through to its default", name)
+            }
+            Warning::AlwaysTrue(name, rule, condition) => {
+                write!(f, "{} rule {} ({}) is always true", name, rule, condition)
+            }
+            Warning::AlwaysFalse(name, rule, condition) => {
+                write!(f, "{} rule {} ({}) is always false", name, rule, condition)
+            }
+            Warning::Cycle(names) => write!(f, "cycle {} -> {}", names.join(" -> "), names[0]),
+        }
+    }
+}
+
+/// What the analysis saw happen at each rule of a workflow
+#[derive(Clone, Default)]
+struct RuleUsage {
+    reached: bool,
+    passed: bool,
+    failed: bool,
+}
+
+/// Workflows with names resolved to indexes, and with any rules no part can
+/// take out of the way
+pub struct Program {
+    pub attributes: Attributes,
+    /// Only decide the same way as the rules as written for parts inside the
+    /// attributes' bounds
+    pub workflows: Vec<Workflow>,
+    /// The rules as written, for parts that could be anywhere
+    written: Vec<Workflow>,
+    pub start: usize,
+    pub warnings: Vec<Warning>,
+}
+
+impl Program {
+    pub fn compile(rules: &RuleSet) -> Result<Self, CompileError> {
+        let mut names: Vec<&String> = rules.rules.keys().collect();
+        names.sort();
+        let index: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(n, name)| (name.as_str(), n))
+            .collect();
+        let start = *index.get("in").ok_or(CompileError::NoStart)?;
+
+        let resolve = |from: &str, to: &str| match to {
+            "A" => Ok(Target::Accept),
+            "R" => Ok(Target::Reject),
+            _ => index.get(to).map(|n| Target::Workflow(*n)).ok_or_else(|| {
+                CompileError::UnknownWorkflow {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                }
+            }),
+        };
+
+        let mut workflows = Vec::new();
+        for name in &names {
+            let rule = &rules.rules[*name];
+            let tests = rule
+                .tests
+                .iter()
+                .map(|test| {
+                    Ok(Test {
+                        condition: test.condition,
+                        target: resolve(name, &test.dest)?,
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            workflows.push(Workflow {
+                name: name.to_string(),
+                tests,
+                default: resolve(name, &rule.default)?,
+            });
+        }
+
+        let mut program = Self {
+            attributes: rules.attributes.clone(),
+            written: workflows.clone(),
+            workflows,
+            start,
+            warnings: Vec::new(),
+        };
+        program.find_cycles();
+        program.remove_dead_rules();
+        Ok(program)
+    }
+
+    fn targets(workflow: &Workflow) -> impl Iterator<Item = Target> + '_ {
+        workflow
+            .tests
+            .iter()
+            .map(|test| test.target)
+            .chain(std::iter::once(workflow.default))
+    }
+
+    /// Depth first search for loops in the graph of workflows, whether or not
+    /// any part could actually go round them
+    fn find_cycles(&mut self) {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Mark {
+            New,
+            OnPath,
+            Done,
+        }
+
+        let mut marks = vec![Mark::New; self.workflows.len()];
+        for root in 0..self.workflows.len() {
+            if marks[root] != Mark::New {
+                continue;
+            }
+            // each frame is a workflow and the targets of it left to visit
+            let mut path = vec![(
+                root,
+                Self::targets(&self.workflows[root]).collect::<Vec<_>>(),
+            )];
+            marks[root] = Mark::OnPath;
+            while let Some((node, targets)) = path.last_mut() {
+                let node = *node;
+                let Some(target) = targets.pop() else {
+                    marks[node] = Mark::Done;
+                    path.pop();
+                    continue;
+                };
+                let Target::Workflow(next) = target else {
+                    continue;
+                };
+                match marks[next] {
+                    Mark::New => {
+                        marks[next] = Mark::OnPath;
+                        let targets = Self::targets(&self.workflows[next]).collect();
+                        path.push((next, targets));
+                    }
+                    Mark::OnPath => {
+                        let from = path.iter().position(|(n, _)| *n == next).unwrap();
+                        let names = path[from..]
+                            .iter()
+                            .map(|(n, _)| self.workflows[*n].name.clone())
+                            .collect();
+                        self.warnings.push(Warning::Cycle(names));
+                    }
+                    Mark::Done => {}
+                }
+            }
+        }
+    }
+
+    /// Pushes every possible part through the workflows to see which rules
+    /// are ever reached, passed and failed, then drops the rules that can
+    /// never decide anything.
+    ///
+    /// A range of parts going round a cycle would loop forever, so ranges are
+    /// followed through no more workflows than there are.
+    fn remove_dead_rules(&mut self) {
+        let mut usage: Vec<Vec<RuleUsage>> = self
+            .workflows
+            .iter()
+            .map(|workflow| vec![RuleUsage::default(); workflow.tests.len() + 1])
+            .collect();
+
+        let mut ranges = vec![(PartRange::new(&self.attributes), self.start, 0)];
+        while let Some((mut parts, n, depth)) = ranges.pop() {
+            let workflow = &self.workflows[n];
+            let mut send = |parts: PartRange, target: Target| {
+                if let Target::Workflow(next) = target {
+                    if depth < self.workflows.len() {
+                        ranges.push((parts, next, depth + 1));
+                    }
+                }
+            };
+
+            let mut fell_through = true;
+            for (test, usage) in workflow.tests.iter().zip(&mut usage[n]) {
+                usage.reached = true;
+                let (pass, fail) = parts.split_with_rule(&test.condition);
+                if let Some(pass) = pass {
+                    usage.passed = true;
+                    send(pass, test.target);
+                }
+                match fail {
+                    Some(fail) => {
+                        usage.failed = true;
+                        parts = fail;
+                    }
+                    None => {
+                        fell_through = false;
+                        break;
+                    }
+                }
+            }
+            if fell_through {
+                usage[n].last_mut().unwrap().reached = true;
+                send(parts, workflow.default);
+            }
+        }
+
+        for (workflow, usage) in self.workflows.iter_mut().zip(usage) {
+            let name = &workflow.name;
+            if !usage.iter().any(|rule| rule.reached) {
+                self.warnings
+                    .push(Warning::UnreachableWorkflow(name.clone()));
+                continue;
+            }
+
+            let mut tests = Vec::new();
+            for (rule, (test, usage)) in workflow.tests.iter().zip(&usage).enumerate() {
+                let (rule, condition) = (rule + 1, test.condition.describe(&self.attributes));
+                if !usage.reached {
+                    self.warnings
+                        .push(Warning::UnreachableRule(name.clone(), rule, condition));
+                } else if !usage.passed {
+                    self.warnings
+                        .push(Warning::AlwaysFalse(name.clone(), rule, condition));
+                } else if !usage.failed {
+                    self.warnings
+                        .push(Warning::AlwaysTrue(name.clone(), rule, condition));
+                    // nothing gets past it, so it may as well be the default
+                    workflow.default = test.target;
+                } else {
+                    tests.push(*test);
+                }
+            }
+            if !usage.last().unwrap().reached {
+                self.warnings
+                    .push(Warning::UnreachableDefault(name.clone()));
+            }
+            workflow.tests = tests;
+        }
+    }
+
+    /// Whether the workflows accept the part. Its ratings may be outside the
+    /// attributes' bounds, so it goes through the rules as written.
+    pub fn process(&self, part: &Part) -> Result<bool, LoopError> {
+        let mut n = self.start;
+        // a part's path is fixed, so visiting more workflows than there are
+        // means it's going round in circles
+        for _ in 0..=self.written.len() {
+            let workflow = &self.written[n];
+            let target = workflow
+                .tests
+                .iter()
+                .find(|test| test.condition.matches(part))
+                .map_or(workflow.default, |test| test.target);
+            match target {
+                Target::Accept => return Ok(true),
+                Target::Reject => return Ok(false),
+                Target::Workflow(next) => n = next,
+            }
+        }
+        Err(LoopError::Part(self.written[n].name.clone()))
+    }
+
+    /// Every part within the attributes' bounds that's accepted, as a list of
+    /// boxes that don't overlap
+    pub fn scan_all(&self) -> Result<Vec<PartRange>, LoopError> {
+        Ok(self.partition(PartRange::new(&self.attributes))?.0)
+    }
+
+    /// Splits `parts` into boxes that are accepted and boxes that are rejected
+    pub fn partition(
+        &self,
+        parts: PartRange,
+    ) -> Result<(Vec<PartRange>, Vec<PartRange>), LoopError> {
+        let mut ranges = vec![(parts, self.start, 0)];
+        let mut passed = Vec::new();
+        let mut failed = Vec::new();
+
+        while let Some((mut parts, n, depth)) = ranges.pop() {
+            if depth > self.workflows.len() {
+                return Err(LoopError::Parts(self.workflows[n].name.clone()));
+            }
+            let workflow = &self.workflows[n];
+            let mut send = |parts: PartRange, target: Target| match target {
+                Target::Accept => passed.push(parts),
+                Target::Reject => failed.push(parts),
+                Target::Workflow(next) => ranges.push((parts, next, depth + 1)),
+            };
+
+            let mut need_default = true;
+            for test in &workflow.tests {
+                let (pass, fail) = parts.split_with_rule(&test.condition);
+                if let Some(pass) = pass {
+                    send(pass, test.target);
+                }
+
+                if let Some(fail) = fail {
+                    parts = fail;
+                } else {
+                    need_default = false;
+                    break; // nothing more to keep chaining on
+                }
+            }
+            if need_default {
+                send(parts, workflow.default);
+            }
+        }
+
+        Ok((passed, failed))
+    }
+}
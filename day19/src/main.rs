@@ -1,126 +1,206 @@
+mod compile;
+
 use std::{collections::HashMap, ops::Range};
 
+use compile::{LoopError, Program};
+
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        if cmd == "check" {
+            check_workflows(text);
+            return;
+        }
+        if cmd == "diff" {
+            let path = args.get(1).expect("Missing file to compare against");
+            let other = std::fs::read_to_string(path).expect("Failed to read comparison file");
+            diff_workflows(text, &other);
+            return;
+        }
+        if cmd == "scan" {
+            scan_workflows(text, &args[1..]);
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
+}
+
+/// The ratings parts are given, in the order they're listed on the parts, and
+/// the values each one can take
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Attributes {
+    names: Vec<String>,
+    bounds: Vec<Range<i64>>,
+}
+
+impl Attributes {
+    /// Ratings the puzzle gives each part
+    const DEFAULT_BOUNDS: Range<i64> = 1..4001;
+
+    /// Every rating named in the parts or tested by the workflows
+    fn from_input(input: &str) -> Self {
+        let (rules, parts) = input.split_once("\n\n").unwrap_or((input, ""));
+        let rated = parts.lines().flat_map(|line| {
+            line.trim_matches(['{', '}'])
+                .split(',')
+                .filter_map(|rating| rating.split_once('=').map(|(name, _)| name))
+        });
+        let tested = rules.lines().flat_map(|line| {
+            let (_, body) = line.split_once('{').unwrap();
+            body.split(',')
+                .filter_map(|rule| rule.split_once(['<', '>']).map(|(name, _)| name))
+        });
+
+        let mut attributes = Self {
+            names: Vec::new(),
+            bounds: Vec::new(),
+        };
+        for name in rated.chain(tested) {
+            if attributes.find(name).is_none() {
+                attributes.names.push(name.to_string());
+                attributes.bounds.push(Self::DEFAULT_BOUNDS);
+            }
+        }
+        attributes
+    }
+
+    fn find(&self, name: &str) -> Option<Var> {
+        self.names.iter().position(|n| n == name).map(Var)
+    }
+
+    fn var(&self, name: &str) -> Var {
+        self.find(name)
+            .unwrap_or_else(|| panic!("Unknown attribute {name}"))
+    }
+
+    fn name(&self, var: Var) -> &str {
+        &self.names[var.0]
+    }
+
+    /// Adds any ratings only `other` has, with their bounds
+    fn merge(&mut self, other: &Attributes) {
+        for (name, bounds) in other.names.iter().zip(&other.bounds) {
+            if self.find(name).is_none() {
+                self.names.push(name.clone());
+                self.bounds.push(bounds.clone());
+            }
+        }
+    }
+
+    /// Limits the values the rating can take to `range`
+    fn set_bounds(&mut self, var: Var, range: Range<i64>) {
+        self.bounds[var.0] = range;
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 struct Part {
-    x: i64,
-    m: i64,
-    a: i64,
-    s: i64,
+    ratings: Vec<i64>,
 }
 
 impl Part {
-    pub fn from_line(line: &str) -> Self {
-        let line = line
-            .replace("{", "")
-            .replace("=", "")
-            .replace("}", "")
-            .replace("x", "")
-            .replace("m", "")
-            .replace("a", "")
-            .replace("s", "");
-        let mut segments = line.split(",");
+    pub fn from_line(line: &str, attributes: &Attributes) -> Self {
+        let mut ratings = vec![None; attributes.names.len()];
+        for rating in line.trim_matches(['{', '}']).split(',') {
+            let (name, value) = rating.split_once('=').unwrap();
+            ratings[attributes.var(name).0] = Some(value.parse().unwrap());
+        }
         Self {
-            x: segments.next().and_then(|s| s.parse().ok()).unwrap(),
-            m: segments.next().and_then(|s| s.parse().ok()).unwrap(),
-            a: segments.next().and_then(|s| s.parse().ok()).unwrap(),
-            s: segments.next().and_then(|s| s.parse().ok()).unwrap(),
+            ratings: ratings
+                .into_iter()
+                .enumerate()
+                .map(|(n, rating)| {
+                    rating.unwrap_or_else(|| panic!("Part {line} has no {}", attributes.names[n]))
+                })
+                .collect(),
         }
     }
 
-    pub fn get_var(self, var: &Var) -> i64 {
-        match var {
-            Var::X => self.x,
-            Var::M => self.m,
-            Var::A => self.a,
-            Var::S => self.s,
-        }
+    pub fn get_var(&self, var: &Var) -> i64 {
+        self.ratings[var.0]
     }
 
     pub fn rating(&self) -> i64 {
-        self.x + self.m + self.a + self.s
+        self.ratings.iter().sum()
+    }
+
+    /// The part as written in the puzzle input
+    fn describe(&self, attributes: &Attributes) -> String {
+        let ratings = self
+            .ratings
+            .iter()
+            .zip(&attributes.names)
+            .map(|(rating, name)| format!("{}={}", name, rating))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", ratings.join(","))
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum Op {
     Greater,
     Less,
 }
 
-enum Var {
-    X,
-    M,
-    A,
-    S,
+/// Index of a rating in the `Attributes`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Var(usize);
+
+/// A comparison of one of a part's ratings against a fixed value
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Condition {
+    op: Op,
+    var: Var,
+    value: i64,
 }
 
-impl Var {
-    fn from_str(s: &str) -> Self {
-        match s {
-            "x" => Self::X,
-            "m" => Self::M,
-            "a" => Self::A,
-            "s" => Self::S,
-            _ => panic!("Unexpected string {s} in Var::from_char()"),
+impl Condition {
+    fn matches(&self, part: &Part) -> bool {
+        match self.op {
+            Op::Less => part.get_var(&self.var) < self.value,
+            Op::Greater => part.get_var(&self.var) > self.value,
         }
     }
+
+    /// The condition as written in the workflows
+    fn describe(&self, attributes: &Attributes) -> String {
+        let op = match self.op {
+            Op::Less => '<',
+            Op::Greater => '>',
+        };
+        format!("{}{}{}", attributes.name(self.var), op, self.value)
+    }
 }
 
 struct RuleSegment {
-    op: Op,
-    var: Var,
-    value: i64,
+    condition: Condition,
     dest: String,
 }
 
 impl RuleSegment {
-    fn from_text(text: &str) -> Self {
-        let (test, dest) = text.split_once(":").unwrap();
-        if let Some((var, val)) = test.split_once("<") {
-            Self {
-                op: Op::Less,
-                var: Var::from_str(var),
-                value: val.parse().unwrap(),
-                dest: dest.to_string(),
-            }
-        } else if let Some((var, val)) = test.split_once(">") {
-            Self {
-                op: Op::Greater,
-                var: Var::from_str(var),
-                value: val.parse().unwrap(),
-                dest: dest.to_string(),
-            }
+    fn from_text(text: &str, attributes: &Attributes) -> Self {
+        let (test, dest) = text.split_once(':').unwrap();
+        let (op, (var, val)) = if let Some(split) = test.split_once('<') {
+            (Op::Less, split)
+        } else if let Some(split) = test.split_once('>') {
+            (Op::Greater, split)
         } else {
             panic!("No Comparison in text {text}");
-        }
-    }
-
-    fn run_rule(&self, part: &Part) -> Option<String> {
-        match self.op {
-            Op::Less => {
-                if part.get_var(&self.var) < self.value {
-                    Some(self.dest.clone())
-                } else {
-                    None
-                }
-            }
-            Op::Greater => {
-                if part.get_var(&self.var) > self.value {
-                    Some(self.dest.clone())
-                } else {
-                    None
-                }
-            }
+        };
+        Self {
+            condition: Condition {
+                op,
+                var: attributes.var(var),
+                value: val.parse().unwrap(),
+            },
+            dest: dest.to_string(),
         }
     }
 }
@@ -131,130 +211,67 @@ struct Rule {
 }
 
 impl Rule {
-    fn from_line(line: &str) -> (String, Self) {
-        let (name, body) = line.split_once("{").unwrap();
-        let (rules, default) = body.rsplit_once(",").unwrap();
+    fn from_line(line: &str, attributes: &Attributes) -> (String, Self) {
+        let (name, body) = line.split_once('{').unwrap();
+        let (rules, default) = body.rsplit_once(',').unwrap();
         let tests = rules
-            .split(",")
-            .map(|r| RuleSegment::from_text(r))
+            .split(',')
+            .map(|rule| RuleSegment::from_text(rule, attributes))
             .collect();
         (
             name.to_string(),
             Self {
                 tests,
-                default: default.replace("}", "").to_string(),
+                default: default.replace('}', ""),
             },
         )
     }
-
-    fn run_rule(&self, part: &Part) -> String {
-        self.tests
-            .iter()
-            .find_map(|test| test.run_rule(part))
-            .unwrap_or_else(|| self.default.clone())
-    }
 }
 
 struct RuleSet {
     rules: HashMap<String, Rule>,
+    attributes: Attributes,
 }
 
 impl RuleSet {
-    fn from_input(input: &str) -> Self {
+    fn from_input(input: &str, attributes: &Attributes) -> Self {
         Self {
-            rules: input.lines().map(|line| Rule::from_line(line)).collect(),
+            rules: input
+                .lines()
+                .map(|line| Rule::from_line(line, attributes))
+                .collect(),
+            attributes: attributes.clone(),
         }
     }
-
-    fn process(&self, part: &Part) -> bool {
-        let mut location = "in".to_string();
-        while location != "A" && location != "R" {
-            location = self.rules.get(&location).unwrap().run_rule(part);
-        }
-
-        location == "A"
-    }
-
-    fn scan_all(&self) -> i64 {
-        let mut ranges = vec![(PartRange::new(), "in".to_string())];
-        let mut passed = 0;
-
-        while let Some((mut parts, key)) = ranges.pop() {
-            let rule = self.rules.get(&key).unwrap();
-            let mut need_default = true;
-            for test in &rule.tests {
-                let (pass, fail) = parts.split_with_rule(test);
-                if let Some(pass) = pass {
-                    if test.dest == "A" {
-                        passed += pass.size();
-                    } else if test.dest == "R" {
-                        // rejected, do nothing
-                    } else {
-                        ranges.push((pass, test.dest.clone()))
-                    };
-                }
-
-                if let Some(fail) = fail {
-                    parts = fail;
-                } else {
-                    need_default = false;
-                    break; // nothing more to keep chaining on
-                }
-            }
-            if need_default {
-                if rule.default == "A" {
-                    passed += parts.size();
-                } else if rule.default == "R" {
-                    // rejected, do nothing
-                } else {
-                    ranges.push((parts, rule.default.clone()))
-                };
-            }
-        }
-
-        passed
-    }
 }
 
-#[derive(Clone)]
+/// Every part with each rating in its range, a box in the space of parts
+#[derive(Clone, Debug, PartialEq, Eq)]
 struct PartRange {
-    x: Range<i64>,
-    m: Range<i64>,
-    a: Range<i64>,
-    s: Range<i64>,
+    ranges: Vec<Range<i64>>,
 }
 
 impl PartRange {
-    fn new() -> Self {
+    /// Every part within the attributes' bounds
+    fn new(attributes: &Attributes) -> Self {
         Self {
-            x: 1..4001,
-            m: 1..4001,
-            a: 1..4001,
-            s: 1..4001,
+            ranges: attributes.bounds.clone(),
         }
     }
 
     fn get_var(&self, var: &Var) -> &Range<i64> {
-        match var {
-            Var::X => &self.x,
-            Var::M => &self.m,
-            Var::A => &self.a,
-            Var::S => &self.s,
-        }
+        &self.ranges[var.0]
     }
 
     fn set_var(&self, var: &Var, range: Range<i64>) -> Self {
         let mut out = self.clone();
-        match var {
-            Var::X => out.x = range,
-            Var::M => out.m = range,
-            Var::A => out.a = range,
-            Var::S => out.s = range,
-        };
+        out.ranges[var.0] = range;
         out
     }
 
-    fn split_with_rule(&self, rule: &RuleSegment) -> (Option<PartRange>, Option<PartRange>) {
+    /// Splits the range into the parts that pass the condition and the parts
+    /// that fail it, either of which may be empty
+    fn split_with_rule(&self, rule: &Condition) -> (Option<PartRange>, Option<PartRange>) {
         let mut split = rule.value;
         if rule.op == Op::Greater {
             split += 1
@@ -275,13 +292,30 @@ impl PartRange {
         }
     }
 
-    fn size(&self) -> i64 {
-        let xsize = self.x.end - self.x.start;
-        let msize = self.m.end - self.m.start;
-        let asize = self.a.end - self.a.start;
-        let ssize = self.s.end - self.s.start;
-        let out = xsize * msize * asize * ssize;
-        out
+    /// How many parts are in the box, which for enough attributes is too many
+    /// to count even in a `u128`
+    fn size(&self) -> Option<u128> {
+        self.ranges.iter().try_fold(1u128, |size, range| {
+            size.checked_mul((range.end - range.start) as u128)
+        })
+    }
+
+    /// The part at the lowest corner of the box
+    fn witness(&self) -> Part {
+        Part {
+            ratings: self.ranges.iter().map(|range| range.start).collect(),
+        }
+    }
+
+    /// The range written as `name=low..high` for each rating, exclusive of
+    /// `high` like a Rust range
+    fn describe(&self, attributes: &Attributes) -> String {
+        self.ranges
+            .iter()
+            .zip(&attributes.names)
+            .map(|(range, name)| format!("{}={}..{}", name, range.start, range.end))
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 }
 
@@ -300,21 +334,158 @@ impl Split for Range<i64> {
     }
 }
 
-fn part1(text: &str) {
-    let (rules, parts) = text.split_once("\n\n").unwrap();
-    let rules = RuleSet::from_input(rules);
+fn compile(rules: &str, attributes: &Attributes) -> Option<Program> {
+    match Program::compile(&RuleSet::from_input(rules, attributes)) {
+        Ok(program) => Some(program),
+        Err(e) => {
+            println!("Invalid workflows: {}", e);
+            None
+        }
+    }
+}
+
+/// The result of running parts through the workflows, printing why if they
+/// loop forever
+fn evaluate<T>(result: Result<T, LoopError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            println!("Can't evaluate workflows: {}", e);
+            None
+        }
+    }
+}
+
+/// Lists everything the compiler found suspicious about the workflows
+fn check_workflows(text: &str) {
+    let attributes = Attributes::from_input(text);
+    let Some(program) = compile(text.split_once("\n\n").unwrap().0, &attributes) else {
+        return;
+    };
+    if program.warnings.is_empty() {
+        println!("No warnings");
+    }
+    for warning in &program.warnings {
+        println!("Warning: {}", warning);
+    }
+}
+
+/// Lists every box of parts the workflows accept. Each argument of the form
+/// `name=low..high` changes the bounds of that rating.
+fn scan_workflows(text: &str, args: &[String]) {
+    let mut attributes = Attributes::from_input(text);
+    for arg in args {
+        let (name, range) = arg.split_once('=').expect("Expected name=low..high");
+        let (low, high) = range.split_once("..").expect("Expected name=low..high");
+        let range = low.parse().expect("Invalid bound")..high.parse().expect("Invalid bound");
+        attributes.set_bounds(attributes.var(name), range);
+    }
+
+    let Some(program) = compile(text.split_once("\n\n").unwrap().0, &attributes) else {
+        return;
+    };
+    let Some(accepted) = evaluate(program.scan_all()) else {
+        return;
+    };
+    for parts in &accepted {
+        println!("{}", parts.describe(&attributes));
+    }
     println!(
-        "{}",
-        parts
-            .lines()
-            .map(|line| Part::from_line(line))
-            .filter(|part| rules.process(part))
-            .map(|part| part.rating())
-            .sum::<i64>(),
+        "{} boxes, {} parts accepted",
+        accepted.len(),
+        count_parts(&accepted)
     );
 }
 
+/// Compares which parts two sets of workflows accept. Each box accepted by one
+/// set is put through the other, and any of it that's rejected is reported
+/// along with a part from it.
+fn diff_workflows(text: &str, other: &str) {
+    let mut attributes = Attributes::from_input(text);
+    attributes.merge(&Attributes::from_input(other));
+    // the other file may leave out the parts
+    fn rules(text: &str) -> &str {
+        text.split_once("\n\n").map_or(text, |(rules, _)| rules)
+    }
+    let (Some(ours), Some(theirs)) = (
+        compile(rules(text), &attributes),
+        compile(rules(other), &attributes),
+    ) else {
+        return;
+    };
+
+    let mut equivalent = true;
+    for (name, first, second) in [("input", &ours, &theirs), ("other", &theirs, &ours)] {
+        let only = first.scan_all().and_then(|accepted| {
+            accepted
+                .into_iter()
+                .map(|parts| Ok(second.partition(parts)?.1))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        let Some(only) = evaluate(only) else {
+            return;
+        };
+        let only: Vec<PartRange> = only.into_iter().flatten().collect();
+        if only.is_empty() {
+            continue;
+        }
+
+        equivalent = false;
+        println!("Only accepted by {}: {} parts", name, count_parts(&only));
+        for parts in &only {
+            println!(
+                "  {} e.g. {}",
+                parts.describe(&attributes),
+                parts.witness().describe(&attributes)
+            );
+        }
+    }
+
+    if equivalent {
+        // both scans already succeeded above
+        println!(
+            "Workflows are equivalent, both accept {} parts",
+            count_parts(&ours.scan_all().unwrap())
+        );
+    }
+}
+
+/// Total parts in boxes that don't overlap
+fn count_parts(boxes: &[PartRange]) -> u128 {
+    boxes
+        .iter()
+        .try_fold(0u128, |total, parts| total.checked_add(parts.size()?))
+        .expect("Too many parts to count")
+}
+
+fn part1(text: &str) {
+    let attributes = Attributes::from_input(text);
+    let (rules, parts) = text.split_once("\n\n").unwrap();
+    let Some(program) = compile(rules, &attributes) else {
+        return;
+    };
+    let total = parts
+        .lines()
+        .map(|line| Part::from_line(line, &attributes))
+        .map(|part| {
+            Ok(if program.process(&part)? {
+                part.rating()
+            } else {
+                0
+            })
+        })
+        .sum::<Result<i64, _>>();
+    if let Some(total) = evaluate(total) {
+        println!("{}", total);
+    }
+}
+
 fn part2(text: &str) {
-    let rules = RuleSet::from_input(text.split_once("\n\n").unwrap().0);
-    println!("{}", rules.scan_all());
+    let attributes = Attributes::from_input(text);
+    let Some(program) = compile(text.split_once("\n\n").unwrap().0, &attributes) else {
+        return;
+    };
+    if let Some(accepted) = evaluate(program.scan_all()) {
+        println!("{}", count_parts(&accepted));
+    }
 }
@@ -1,18 +1,78 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt,
+};
 
 fn main() {
     let text = include_str!("../input");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(cmd) = args.first() {
+        if cmd == "stats" {
+            let presses = args.get(1).map_or(1000, |presses| {
+                presses.parse().expect("Invalid press count")
+            });
+            let mut modules = ModuleSet::from_input(text);
+            for _ in 0..presses {
+                modules.press_button();
+            }
+            modules.show_stats();
+            return;
+        }
+        if cmd == "trace" {
+            let presses = args
+                .get(1)
+                .map_or(1, |presses| presses.parse().expect("Invalid press count"));
+            let mut modules = ModuleSet::with_log(text);
+            for _ in 0..presses {
+                modules.press_button();
+            }
+            for pulse in modules.log.as_ref().unwrap() {
+                println!("{}", modules.format_pulse(pulse));
+            }
+            return;
+        }
+        if cmd == "watch" {
+            watch(text, &args[1..]);
+            return;
+        }
+        if cmd == "repeat" {
+            let max_presses = args.get(1).map_or(PERIOD_SEARCH_LIMIT as u32, |max| {
+                max.parse().expect("Invalid press count")
+            });
+            match ModuleSet::from_input(text).find_repeat(max_presses) {
+                Some((first, period)) => println!(
+                    "State after press {} repeats every {} presses",
+                    first, period
+                ),
+                None => println!("No repeated state in {} presses", max_presses),
+            }
+            return;
+        }
+        if cmd == "checkpoint" || cmd == "resume" {
+            run_from_checkpoint(text, &args);
+            return;
+        }
+        if cmd == "dot" {
+            let dot = ModuleSet::from_input(text).to_dot();
+            match args.get(1) {
+                Some(path) => std::fs::write(path, dot).expect("Failed to write graph"),
+                None => print!("{}", dot),
+            }
+            return;
+        }
+    }
+
     println!("Part 1:");
-    part1(&text);
+    part1(text);
 
     println!("\nPart 2:");
-    part2(&text);
+    part2(text);
 }
 
 type ModuleIdx = usize;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 enum PulseType {
     High,
     Low,
@@ -25,6 +85,23 @@ struct Pulse {
     pulse: PulseType,
 }
 
+impl PulseType {
+    fn from_str(pulse: &str) -> Self {
+        match pulse {
+            "low" => Self::Low,
+            "high" => Self::High,
+            _ => panic!("Unexpected pulse {pulse}, expected low or high"),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            PulseType::Low => "low",
+            PulseType::High => "high",
+        }
+    }
+}
+
 impl Pulse {
     fn new(source: ModuleIdx, dest: ModuleIdx, pulse: PulseType) -> Self {
         Self {
@@ -35,20 +112,30 @@ impl Pulse {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 enum ModuleType {
+    /// Sends a single low pulse to the broadcaster each time it's pressed
+    Button,
     Broadcast,
     FlipFlop {
         on: bool,
     },
+    /// Remembers the last pulse from each of its inputs, in order of the
+    /// inputs' indexes
     Conjunction {
-        source: HashMap<ModuleIdx, PulseType>,
+        source: BTreeMap<ModuleIdx, PulseType>,
+    },
+    /// A destination not defined in the input, recording the pulses it has
+    /// received during the current button press
+    Output {
+        received: Vec<PulseType>,
     },
 }
 
 impl ModuleType {
     fn process_pulse(&mut self, pulse: &Pulse) -> Option<PulseType> {
         match self {
+            ModuleType::Button => panic!("Nothing should send pulses to the button"),
             ModuleType::Broadcast => Some(pulse.pulse),
             ModuleType::FlipFlop { on } => {
                 if pulse.pulse == PulseType::Low {
@@ -70,11 +157,38 @@ impl ModuleType {
                     Some(PulseType::High)
                 }
             }
+            ModuleType::Output { received } => {
+                received.push(pulse.pulse);
+                None
+            }
         }
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+/// Numbers of low and high pulses
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct PulseCounts {
+    low: u64,
+    high: u64,
+}
+
+impl PulseCounts {
+    fn add(&mut self, pulse: PulseType) {
+        match pulse {
+            PulseType::Low => self.low += 1,
+            PulseType::High => self.high += 1,
+        }
+    }
+}
+
+/// Pulses a module has sent and received since the network was created
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct ModuleStats {
+    sent: PulseCounts,
+    received: PulseCounts,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 struct Module {
     index: ModuleIdx,
     mod_type: ModuleType,
@@ -95,12 +209,76 @@ impl Module {
 }
 
 struct ModuleSet {
+    button: ModuleIdx,
     broadcast: ModuleIdx,
     modules: Vec<Module>,
+    names: Vec<String>,
     pulses: VecDeque<Pulse>,
-    low_pulses_sent: u32,
-    high_pulses_sent: u32,
+    stats: Vec<ModuleStats>,
     button_presses: u32,
+    /// Every pulse sent so far, when logging is turned on
+    log: Option<Vec<Pulse>>,
+}
+
+/// Everything the modules remember between button presses, in order of module
+/// index: whether each flip-flop is on, and the last pulse each conjunction got
+/// from each of its inputs
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct NetworkState {
+    flip_flops: Vec<bool>,
+    memories: Vec<Vec<PulseType>>,
+}
+
+impl NetworkState {
+    /// One line of `0`s and `1`s for the flip-flops, then one for the
+    /// conjunctions with each one's memory followed by a `,`, so a network
+    /// without any conjunctions has an empty line
+    fn to_text(&self) -> String {
+        let bit = |on: bool| if on { '1' } else { '0' };
+        let flip_flops: String = self.flip_flops.iter().map(|on| bit(*on)).collect();
+        let memories: String = self
+            .memories
+            .iter()
+            .map(|memory| {
+                let bits: String = memory
+                    .iter()
+                    .map(|pulse| bit(*pulse == PulseType::High))
+                    .collect();
+                bits + ","
+            })
+            .collect();
+        format!("{}\n{}", flip_flops, memories)
+    }
+
+    fn from_text(flip_flops: &str, memories: &str) -> Self {
+        let bit = |c: char| match c {
+            '0' => false,
+            '1' => true,
+            _ => panic!("Unexpected char {c} in network state"),
+        };
+        let pulse = |c: char| {
+            if bit(c) {
+                PulseType::High
+            } else {
+                PulseType::Low
+            }
+        };
+        Self {
+            flip_flops: flip_flops.chars().map(bit).collect(),
+            memories: memories
+                .split_terminator(',')
+                .map(|memory| memory.chars().map(pulse).collect())
+                .collect(),
+        }
+    }
+}
+
+/// A module sending a particular kind of pulse. Output modules never send
+/// anything, so for them it's receiving the pulse instead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Watchpoint {
+    module: ModuleIdx,
+    pulse: PulseType,
 }
 
 impl ModuleSet {
@@ -150,95 +328,596 @@ impl ModuleSet {
             })
             .collect();
 
-        let mut name_to_numbers: HashMap<String, ModuleIdx> = HashMap::new();
-        let mut broadcast = 0;
-        for name in str_modules.keys() {
-            if name == "broadcaster" {
-                broadcast = name_to_numbers.len();
-            }
-            name_to_numbers.insert(name.clone(), name_to_numbers.len());
-        }
+        // Number the modules in the input, then anything they send to that
+        // isn't defined, then the button
+        let mut names: Vec<String> = str_modules.keys().cloned().collect();
+        names.sort();
+        let mut outputs: Vec<String> = str_modules
+            .values()
+            .flat_map(|strmod| strmod.dests.split(", "))
+            .filter(|dst| !str_modules.contains_key(*dst))
+            .map(str::to_string)
+            .collect();
+        outputs.sort();
+        outputs.dedup();
+        names.extend(outputs);
+        names.push("button".to_string());
+
+        let name_to_numbers: HashMap<&str, ModuleIdx> = names
+            .iter()
+            .enumerate()
+            .map(|(n, name)| (name.as_str(), n))
+            .collect();
+        let broadcast = name_to_numbers["broadcaster"];
+        let button = names.len() - 1;
 
         // Construct real modules out of the maps
-        let mut modules: Vec<_> = str_modules
+        let mut modules: Vec<_> = names
             .iter()
-            .map(|(name, strmod)| {
+            .enumerate()
+            .map(|(index, name)| {
+                let Some(strmod) = str_modules.get(name) else {
+                    let (mod_type, dests) = if index == button {
+                        (ModuleType::Button, vec![broadcast])
+                    } else {
+                        let received = Vec::new();
+                        (ModuleType::Output { received }, Vec::new())
+                    };
+                    return Module {
+                        index,
+                        mod_type,
+                        dests,
+                    };
+                };
                 let mod_type = match strmod.tpe {
                     StrModType::Broadcast => ModuleType::Broadcast,
                     StrModType::Conjunction => ModuleType::Conjunction {
-                        source: HashMap::new(),
+                        source: BTreeMap::new(),
                     },
                     StrModType::FlipFlop => ModuleType::FlipFlop { on: false },
                 };
                 let dests: Vec<_> = strmod
                     .dests
                     .split(", ")
-                    .map(|dst| *name_to_numbers.get(dst).unwrap_or(&usize::MAX))
+                    .map(|dst| name_to_numbers[dst])
                     .collect();
                 Module {
-                    index: *name_to_numbers.get(name).unwrap(),
+                    index,
                     mod_type,
                     dests,
                 }
             })
             .collect();
-        modules.sort_by_key(|module| module.index);
 
         // populate the sources for conjunction modules
         for i in 0..modules.len() {
             let sourcemod = modules[i].clone();
             for dst in sourcemod.dests {
-                if dst != usize::MAX {
-                    if let ModuleType::Conjunction { source } = &mut modules[dst].mod_type {
-                        source.insert(sourcemod.index, PulseType::Low);
-                    }
+                if let ModuleType::Conjunction { source } = &mut modules[dst].mod_type {
+                    source.insert(sourcemod.index, PulseType::Low);
                 }
             }
         }
 
+        let stats = vec![ModuleStats::default(); modules.len()];
         Self {
+            button,
             broadcast,
             modules,
+            names,
             pulses: VecDeque::new(),
-            low_pulses_sent: 0,
-            high_pulses_sent: 0,
+            stats,
             button_presses: 0,
+            log: None,
         }
     }
 
-    fn process_pulse(&mut self, pulse: &Pulse) -> bool {
-        if pulse.dest == usize::MAX {
-            if pulse.pulse == PulseType::Low {
-                return true;
-            } else {
-                return false;
-            }
-        };
+    /// A module set that logs every pulse sent
+    fn with_log(input: &str) -> Self {
+        Self {
+            log: Some(Vec::new()),
+            ..Self::from_input(input)
+        }
+    }
+
+    /// The pulse as written in the puzzle, like `broadcaster -low-> a`
+    fn format_pulse(&self, pulse: &Pulse) -> String {
+        format!(
+            "{} -{}-> {}",
+            self.names[pulse.source],
+            pulse.pulse.as_str(),
+            self.names[pulse.dest]
+        )
+    }
+
+    fn find(&self, name: &str) -> Option<ModuleIdx> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    fn process_pulse(&mut self, pulse: &Pulse) {
+        self.stats[pulse.source].sent.add(pulse.pulse);
+        self.stats[pulse.dest].received.add(pulse.pulse);
         self.pulses
-            .append(&mut (self.modules[pulse.dest].process_pulse(&pulse)));
+            .append(&mut (self.modules[pulse.dest].process_pulse(pulse)));
+    }
 
-        return false;
+    /// Presses the button and runs until every pulse has been handled
+    fn press_button(&mut self) {
+        self.press_button_with(|_| {})
     }
 
-    fn press_button(&mut self) -> bool {
+    /// Presses the button, handing each pulse to `on_pulse` as it's sent
+    fn press_button_with(&mut self, mut on_pulse: impl FnMut(&Pulse)) {
         self.button_presses += 1;
+        for module in &mut self.modules {
+            if let ModuleType::Output { received } = &mut module.mod_type {
+                received.clear();
+            }
+        }
+
         self.pulses
-            .push_back(Pulse::new(self.broadcast, self.broadcast, PulseType::Low));
+            .push_back(Pulse::new(self.button, self.broadcast, PulseType::Low));
         while let Some(pulse) = self.pulses.pop_front() {
-            if pulse.pulse == PulseType::Low {
-                self.low_pulses_sent += 1;
-            } else {
-                self.high_pulses_sent += 1;
+            on_pulse(&pulse);
+            if let Some(log) = &mut self.log {
+                log.push(pulse);
             }
-            if self.process_pulse(&pulse) == true {
-                return true;
+            self.process_pulse(&pulse);
+        }
+    }
+
+    /// Presses the button until the watched module sends its pulse, up to
+    /// `max_presses` times. Returns the press it happened on, along with how
+    /// many pulses into that press. The press is always run to the end.
+    fn run_until(&mut self, watch: &Watchpoint, max_presses: u32) -> Option<(u32, usize)> {
+        let is_output = matches!(
+            self.modules[watch.module].mod_type,
+            ModuleType::Output { .. }
+        );
+        for _ in 0..max_presses {
+            let mut sent = 0;
+            let mut hit = None;
+            self.press_button_with(|pulse| {
+                sent += 1;
+                let module = if is_output { pulse.dest } else { pulse.source };
+                if hit.is_none() && module == watch.module && pulse.pulse == watch.pulse {
+                    hit = Some(sent);
+                }
+            });
+            if let Some(hit) = hit {
+                return Some((self.button_presses, hit));
             }
         }
-        return false;
+        None
     }
 
-    fn pulse_product(&self) -> u32 {
-        self.low_pulses_sent * self.high_pulses_sent
+    /// The network as a Graphviz graph, with each kind of module drawn in its
+    /// own shape and colour
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph modules {\n");
+        for module in &self.modules {
+            let style = match module.mod_type {
+                ModuleType::Button => "shape=plaintext",
+                ModuleType::Broadcast => "shape=doublecircle, style=filled, fillcolor=gold",
+                ModuleType::FlipFlop { .. } => "shape=box, style=filled, fillcolor=lightblue",
+                ModuleType::Conjunction { .. } => {
+                    "shape=invtriangle, style=filled, fillcolor=salmon"
+                }
+                ModuleType::Output { .. } => {
+                    "shape=doubleoctagon, style=filled, fillcolor=palegreen"
+                }
+            };
+            dot += &format!("    \"{}\" [{}];\n", self.names[module.index], style);
+        }
+        for module in &self.modules {
+            for dest in &module.dests {
+                dot += &format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    self.names[module.index], self.names[*dest]
+                );
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// Modules that send pulses to `module`
+    fn sources(&self, module: ModuleIdx) -> Vec<ModuleIdx> {
+        self.modules
+            .iter()
+            .filter(|m| m.dests.contains(&module))
+            .map(|m| m.index)
+            .collect()
+    }
+
+    /// Every module upstream of `module`, not counting the broadcaster or the
+    /// button
+    fn upstream(&self, module: ModuleIdx) -> HashSet<ModuleIdx> {
+        let mut seen = HashSet::from([module]);
+        let mut stack = vec![module];
+        while let Some(m) = stack.pop() {
+            for source in self.sources(m) {
+                if source != self.broadcast && seen.insert(source) {
+                    stack.push(source);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Button presses until `sink` first gets a low pulse, for networks where
+    /// the sink is fed by a single conjunction, and each input to that
+    /// conjunction is driven by its own separate counter. Each counter sends
+    /// the conjunction one high pulse, then resets to low, every `period`
+    /// presses, so the sink first gets a low pulse at the LCM of the periods.
+    ///
+    /// Simulation measures the periods, and checks the counters share no
+    /// modules and fire at exactly the multiples of their period.
+    fn presses_until_low(&self, sink: &str) -> Result<u64, AnalysisError> {
+        let sink = match self.find(sink) {
+            Some(n) if matches!(self.modules[n].mod_type, ModuleType::Output { .. }) => n,
+            _ => return Err(AnalysisError::NoSink(sink.to_string())),
+        };
+        let collector = match self.sources(sink).as_slice() {
+            [collector] => *collector,
+            feeders => {
+                let names = feeders.iter().map(|n| self.names[*n].clone()).collect();
+                return Err(AnalysisError::SeveralFeeders(names));
+            }
+        };
+        if !matches!(
+            self.modules[collector].mod_type,
+            ModuleType::Conjunction { .. }
+        ) {
+            return Err(AnalysisError::NotConjunction(self.names[collector].clone()));
+        }
+
+        let counters = self.sources(collector);
+        let upstream: Vec<_> = counters.iter().map(|c| self.upstream(*c)).collect();
+        for (i, a) in upstream.iter().enumerate() {
+            for (j, b) in upstream.iter().enumerate().skip(i + 1) {
+                if !a.is_disjoint(b) {
+                    return Err(AnalysisError::SharedModules(
+                        self.names[counters[i]].clone(),
+                        self.names[counters[j]].clone(),
+                    ));
+                }
+            }
+        }
+
+        // presses on which each counter sent the collector a high pulse
+        let mut firings: Vec<Vec<u64>> = vec![Vec::new(); counters.len()];
+        let mut modules = self.clone_reset();
+        for press in 1..=PERIOD_SEARCH_LIMIT {
+            let mut highs = vec![0; counters.len()];
+            let mut last = vec![None; counters.len()];
+            modules.press_button_with(|pulse| {
+                if pulse.dest != collector {
+                    return;
+                }
+                if let Some(n) = counters.iter().position(|c| *c == pulse.source) {
+                    if pulse.pulse == PulseType::High {
+                        highs[n] += 1;
+                    }
+                    last[n] = Some(pulse.pulse);
+                }
+            });
+
+            for (n, highs) in highs.into_iter().enumerate() {
+                if highs == 0 {
+                    continue;
+                }
+                if highs > 1 || last[n] != Some(PulseType::Low) {
+                    return Err(AnalysisError::NotCounter(self.names[counters[n]].clone()));
+                }
+                firings[n].push(press);
+            }
+            if firings.iter().all(|f| f.len() >= 3) {
+                break;
+            }
+        }
+
+        let mut answer = 1;
+        for (n, firings) in firings.iter().enumerate() {
+            let name = &self.names[counters[n]];
+            let Some(&period) = firings.first() else {
+                return Err(AnalysisError::NotCounter(name.clone()));
+            };
+            let expected = (1..=firings.len() as u64).map(|k| k * period);
+            if firings.len() < 3 || !firings.iter().copied().eq(expected) {
+                return Err(AnalysisError::NotCounter(name.clone()));
+            }
+            answer = lcm(answer, period);
+        }
+        Ok(answer)
+    }
+
+    fn state(&self) -> NetworkState {
+        let mut state = NetworkState {
+            flip_flops: Vec::new(),
+            memories: Vec::new(),
+        };
+        for module in &self.modules {
+            match &module.mod_type {
+                ModuleType::FlipFlop { on } => state.flip_flops.push(*on),
+                ModuleType::Conjunction { source } => {
+                    state.memories.push(source.values().copied().collect())
+                }
+                ModuleType::Button | ModuleType::Broadcast | ModuleType::Output { .. } => {}
+            }
+        }
+        state
+    }
+
+    /// Puts every flip-flop and conjunction back as they were in `state`
+    fn restore(&mut self, state: &NetworkState) {
+        let mut flip_flops = state.flip_flops.iter();
+        let mut memories = state.memories.iter();
+        for module in &mut self.modules {
+            match &mut module.mod_type {
+                ModuleType::FlipFlop { on } => {
+                    *on = *flip_flops.next().expect("State has too few flip-flops")
+                }
+                ModuleType::Conjunction { source } => {
+                    let memory = memories.next().expect("State has too few conjunctions");
+                    assert_eq!(
+                        memory.len(),
+                        source.len(),
+                        "Conjunction memory is wrong size"
+                    );
+                    for (pulse, remembered) in source.values_mut().zip(memory) {
+                        *pulse = *remembered;
+                    }
+                }
+                ModuleType::Button | ModuleType::Broadcast | ModuleType::Output { .. } => {}
+            }
+        }
+        assert!(
+            flip_flops.next().is_none() && memories.next().is_none(),
+            "State has modules this network doesn't"
+        );
+    }
+
+    /// Presses the button until the network gets back to a state it's been in
+    /// before, up to `max_presses` times. Returns how many presses it took to
+    /// first reach the repeated state, and how many presses it repeats after.
+    fn find_repeat(&mut self, max_presses: u32) -> Option<(u32, u32)> {
+        let mut seen = HashMap::from([(self.state(), self.button_presses)]);
+        for _ in 0..max_presses {
+            self.press_button();
+            if let Some(first) = seen.insert(self.state(), self.button_presses) {
+                return Some((first, self.button_presses - first));
+            }
+        }
+        None
+    }
+
+    /// The presses made, pulse statistics and network state, to pick up a
+    /// simulation from later with `resume`
+    fn to_checkpoint(&self) -> String {
+        let mut checkpoint = format!("{}\n", self.button_presses);
+        for stats in &self.stats {
+            checkpoint += &format!(
+                "{} {} {} {}\n",
+                stats.sent.low, stats.sent.high, stats.received.low, stats.received.high
+            );
+        }
+        checkpoint += &self.state().to_text();
+        checkpoint += "\n";
+        checkpoint
+    }
+
+    /// The network from `input`, as it was when `checkpoint` was written
+    fn resume(input: &str, checkpoint: &str) -> Self {
+        let mut modules = Self::from_input(input);
+        let mut lines = checkpoint.lines();
+        modules.button_presses = lines
+            .next()
+            .and_then(|presses| presses.parse().ok())
+            .expect("Invalid press count in checkpoint");
+        for stats in &mut modules.stats {
+            let counts: Vec<u64> = lines
+                .next()
+                .expect("Checkpoint has too few modules")
+                .split_whitespace()
+                .map(|count| count.parse().expect("Invalid pulse count in checkpoint"))
+                .collect();
+            let [low_sent, high_sent, low_received, high_received] = counts[..] else {
+                panic!("Expected four pulse counts per module in checkpoint");
+            };
+            stats.sent = PulseCounts {
+                low: low_sent,
+                high: high_sent,
+            };
+            stats.received = PulseCounts {
+                low: low_received,
+                high: high_received,
+            };
+        }
+        let flip_flops = lines.next().expect("Checkpoint has no flip-flop states");
+        let memories = lines.next().unwrap_or("");
+        modules.restore(&NetworkState::from_text(flip_flops, memories));
+        modules
+    }
+
+    /// A copy of the network as it was before any presses
+    fn clone_reset(&self) -> Self {
+        let mut modules = self.modules.clone();
+        for module in &mut modules {
+            match &mut module.mod_type {
+                ModuleType::Button | ModuleType::Broadcast => {}
+                ModuleType::FlipFlop { on } => *on = false,
+                ModuleType::Conjunction { source } => source
+                    .values_mut()
+                    .for_each(|pulse| *pulse = PulseType::Low),
+                ModuleType::Output { received } => received.clear(),
+            }
+        }
+        Self {
+            button: self.button,
+            broadcast: self.broadcast,
+            modules,
+            names: self.names.clone(),
+            pulses: VecDeque::new(),
+            stats: vec![ModuleStats::default(); self.modules.len()],
+            button_presses: 0,
+            log: self.log.as_ref().map(|_| Vec::new()),
+        }
+    }
+
+    /// Pulses sent by every module put together
+    fn total_sent(&self) -> PulseCounts {
+        let mut total = PulseCounts::default();
+        for stats in &self.stats {
+            total.low += stats.sent.low;
+            total.high += stats.sent.high;
+        }
+        total
+    }
+
+    fn pulse_product(&self) -> u64 {
+        let total = self.total_sent();
+        total.low * total.high
+    }
+
+    /// Table of the pulses each module has sent and received
+    fn show_stats(&self) {
+        println!(
+            "{:<16} {:>12} {:>12} {:>12} {:>12}",
+            "module", "low sent", "high sent", "low recv", "high recv"
+        );
+        for (name, stats) in self.names.iter().zip(&self.stats) {
+            println!(
+                "{:<16} {:>12} {:>12} {:>12} {:>12}",
+                name, stats.sent.low, stats.sent.high, stats.received.low, stats.received.high
+            );
+        }
+        let total = self.total_sent();
+        println!("{:<16} {:>12} {:>12}", "total", total.low, total.high);
+    }
+}
+
+/// Presses to simulate while looking for each counter's period
+static PERIOD_SEARCH_LIMIT: u64 = 100_000;
+
+/// Ways a network can fail to be the shape `presses_until_low` relies on
+#[derive(Debug)]
+enum AnalysisError {
+    /// There's no output module with the sink's name
+    NoSink(String),
+    /// The sink is fed by more than one module
+    SeveralFeeders(Vec<String>),
+    /// The module feeding the sink isn't a conjunction
+    NotConjunction(String),
+    /// Two counters share some modules, so needn't keep to their own periods
+    SharedModules(String, String),
+    /// The module doesn't send a single high pulse, followed by a low, at
+    /// regular intervals
+    NotCounter(String),
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::NoSink(name) => write!(f, "no output module named {}", name),
+            AnalysisError::SeveralFeeders(names) => {
+                write!(f, "sink is fed by several modules: {}", names.join(", "))
+            }
+            AnalysisError::NotConjunction(name) => {
+                write!(f, "sink is fed by {}, which isn't a conjunction", name)
+            }
+            AnalysisError::SharedModules(a, b) => {
+                write!(f, "counters {} and {} share modules", a, b)
+            }
+            AnalysisError::NotCounter(name) => write!(
+                f,
+                "{} doesn't fire regularly within {} presses",
+                name, PERIOD_SEARCH_LIMIT
+            ),
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    a / gcd(a, b) * b
+}
+
+/// `checkpoint PRESSES PATH` presses the button from the start and saves the
+/// network to `PATH`. `resume PATH PRESSES [OUT]` carries on from the network
+/// saved in `PATH`, saving it to `OUT` afterwards if given.
+fn run_from_checkpoint(text: &str, args: &[String]) {
+    let arg = |n: usize| args.get(n).expect("Missing argument").as_str();
+    let presses_arg = if args[0] == "checkpoint" { 1 } else { 2 };
+    let presses: u32 = arg(presses_arg).parse().expect("Invalid press count");
+    let (mut modules, out) = if args[0] == "checkpoint" {
+        (ModuleSet::from_input(text), Some(arg(2)))
+    } else {
+        let checkpoint = std::fs::read_to_string(arg(1)).expect("Failed to read checkpoint");
+        (
+            ModuleSet::resume(text, &checkpoint),
+            args.get(3).map(String::as_str),
+        )
+    };
+
+    for _ in 0..presses {
+        modules.press_button();
+    }
+    let total = modules.total_sent();
+    println!(
+        "After {} presses: {} low and {} high pulses sent, product {}",
+        modules.button_presses,
+        total.low,
+        total.high,
+        modules.pulse_product()
+    );
+    if let Some(out) = out {
+        std::fs::write(out, modules.to_checkpoint()).expect("Failed to write checkpoint");
+    }
+}
+
+/// Presses the button until a module sends a pulse: `MODULE low|high [MAX]`
+fn watch(text: &str, args: &[String]) {
+    let mut modules = ModuleSet::from_input(text);
+    let name = args.first().expect("Missing module to watch");
+    let watch = Watchpoint {
+        module: modules
+            .find(name)
+            .unwrap_or_else(|| panic!("No module named {name}")),
+        pulse: PulseType::from_str(args.get(1).expect("Missing pulse to watch for")),
+    };
+    let max_presses = args.get(2).map_or(PERIOD_SEARCH_LIMIT as u32, |max| {
+        max.parse().expect("Invalid press count")
+    });
+
+    let verb = match modules.modules[watch.module].mod_type {
+        ModuleType::Output { .. } => "received",
+        _ => "sent",
+    };
+
+    match modules.run_until(&watch, max_presses) {
+        Some((press, pulse)) => println!(
+            "{} {} a {} pulse on press {}, pulse {} of the press",
+            name,
+            verb,
+            watch.pulse.as_str(),
+            press,
+            pulse
+        ),
+        None => println!(
+            "{} {} no {} pulse in {} presses",
+            name,
+            verb,
+            watch.pulse.as_str(),
+            max_presses
+        ),
     }
 }
 
@@ -252,8 +931,9 @@ fn part1(text: &str) {
 }
 
 fn part2(text: &str) {
-    let mut modules = ModuleSet::from_input(text);
-    while !modules.press_button() {}
-
-    println!("{}", modules.button_presses);
+    let modules = ModuleSet::from_input(text);
+    match modules.presses_until_low("rx") {
+        Ok(presses) => println!("{}", presses),
+        Err(e) => println!("Can't analyse network: {}", e),
+    }
 }